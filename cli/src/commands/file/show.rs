@@ -174,11 +174,10 @@ fn write_tree_entries(
                 copy_async_to_sync(file.reader, ui.stdout_formatter().as_mut()).block_on()?;
             }
             MaterializedTreeValue::FileConflict(file) => {
-                let options = ConflictMaterializeOptions {
-                    marker_style: workspace_command.env().conflict_marker_style(),
-                    marker_len: None,
-                    merge: repo.store().merge_options().clone(),
-                };
+                let options = ConflictMaterializeOptions::new(
+                    workspace_command.env().conflict_marker_style(),
+                    repo.store().merge_options().clone(),
+                );
                 materialize_merge_result(&file.contents, &mut ui.stdout_formatter(), &options)?;
             }
             MaterializedTreeValue::OtherConflict { id } => {
@@ -559,11 +559,10 @@ impl<'a> DiffRenderer<'a> {
             return Ok(());
         }
         const DUMMY_PATH: &str = "JJ-COMMIT-DESCRIPTION";
-        let materialize_options = ConflictMaterializeOptions {
-            marker_style: self.conflict_marker_style,
-            marker_len: None,
-            merge: self.repo.store().merge_options().clone(),
-        };
+        let materialize_options = ConflictMaterializeOptions::new(
+            self.conflict_marker_style,
+            self.repo.store().merge_options().clone(),
+        );
         for format in &self.formats {
             match format {
                 // Omit diff from "short" formats. Printing dummy file path
@@ -1323,11 +1322,8 @@ pub async fn show_color_words_diff(
     options: &ColorWordsDiffOptions,
     marker_style: ConflictMarkerStyle,
 ) -> Result<(), DiffRenderError> {
-    let materialize_options = ConflictMaterializeOptions {
-        marker_style,
-        marker_len: None,
-        merge: store.merge_options().clone(),
-    };
+    let materialize_options =
+        ConflictMaterializeOptions::new(marker_style, store.merge_options().clone());
     let empty_content = || Merge::resolved(BString::default());
     let mut diff_stream = materialized_diff_stream(store, tree_diff);
     while let Some(MaterializedTreeDiffEntry { path, values }) = diff_stream.next().await {
@@ -1477,11 +1473,8 @@ pub async fn show_file_by_file_diff(
     marker_style: ConflictMarkerStyle,
     width: usize,
 ) -> Result<(), DiffRenderError> {
-    let materialize_options = ConflictMaterializeOptions {
-        marker_style,
-        marker_len: None,
-        merge: store.merge_options().clone(),
-    };
+    let materialize_options =
+        ConflictMaterializeOptions::new(marker_style, store.merge_options().clone());
     let create_file = |path: &RepoPath,
                        wc_dir: &Path,
                        value: MaterializedTreeValue|
@@ -1638,11 +1631,8 @@ pub async fn show_git_diff(
     options: &UnifiedDiffOptions,
     marker_style: ConflictMarkerStyle,
 ) -> Result<(), DiffRenderError> {
-    let materialize_options = ConflictMaterializeOptions {
-        marker_style,
-        marker_len: None,
-        merge: store.merge_options().clone(),
-    };
+    let materialize_options =
+        ConflictMaterializeOptions::new(marker_style, store.merge_options().clone());
     let mut diff_stream = materialized_diff_stream(store, tree_diff);
     while let Some(MaterializedTreeDiffEntry { path, values }) = diff_stream.next().await {
         let left_path = path.source();
@@ -1817,11 +1807,8 @@ impl DiffStats {
         options: &DiffStatOptions,
         marker_style: ConflictMarkerStyle,
     ) -> BackendResult<Self> {
-        let materialize_options = ConflictMaterializeOptions {
-            marker_style,
-            marker_len: None,
-            merge: store.merge_options().clone(),
-        };
+        let materialize_options =
+            ConflictMaterializeOptions::new(marker_style, store.merge_options().clone());
         let entries = materialized_diff_stream(store, tree_diff)
             .map(|MaterializedTreeDiffEntry { path, values }| {
                 let (left, right) = values?;
@@ -265,11 +265,8 @@ async fn make_diff_files(
     tree_diff: BoxStream<'_, CopiesTreeDiffEntry>,
     marker_style: ConflictMarkerStyle,
 ) -> Result<(Vec<RepoPathBuf>, Vec<scm_record::File<'static>>), BuiltinToolError> {
-    let materialize_options = ConflictMaterializeOptions {
-        marker_style,
-        marker_len: None,
-        merge: store.merge_options().clone(),
-    };
+    let materialize_options =
+        ConflictMaterializeOptions::new(marker_style, store.merge_options().clone());
     let mut diff_stream = materialized_diff_stream(store, tree_diff);
     let mut changed_files = Vec::new();
     let mut files = Vec::new();
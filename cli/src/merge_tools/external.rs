@@ -9,7 +9,6 @@ use std::sync::Arc;
 
 use bstr::BString;
 use itertools::Itertools as _;
-use jj_lib::backend::CopyId;
 use jj_lib::backend::TreeValue;
 use jj_lib::conflicts;
 use jj_lib::conflicts::ConflictMarkerStyle;
@@ -205,11 +204,13 @@ fn run_mergetool_external_single_file(
     };
     let initial_output_content = if editor.merge_tool_edits_conflict_markers {
         let options = ConflictMaterializeOptions {
-            marker_style: editor
-                .conflict_marker_style
-                .unwrap_or(default_conflict_marker_style),
             marker_len: Some(conflict_marker_len),
-            merge: store.merge_options().clone(),
+            ..ConflictMaterializeOptions::new(
+                editor
+                    .conflict_marker_style
+                    .unwrap_or(default_conflict_marker_style),
+                store.merge_options().clone(),
+            )
         };
         materialize_merge_result_to_bytes(&file.contents, &options)
     } else {
@@ -317,10 +318,11 @@ fn run_mergetool_external_single_file(
     let new_tree_value = match new_file_ids.into_resolved() {
         Ok(file_id) => {
             let executable = file.executable.expect("should have been resolved");
+            let copy_id = file.copy_id.clone().expect("should have been resolved");
             Merge::resolved(file_id.map(|id| TreeValue::File {
                 id,
                 executable,
-                copy_id: CopyId::placeholder(),
+                copy_id,
             }))
         }
         // Update the file ids only, leaving the executable flags unchanged
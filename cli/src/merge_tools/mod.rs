@@ -106,6 +106,8 @@ pub enum ConflictResolveError {
     ConflictTooComplicated { path: RepoPathBuf, sides: usize },
     #[error("{path:?} has conflicts in executable bit\n{summary}", summary = summary.trim_end())]
     ExecutableConflict { path: RepoPathBuf, summary: String },
+    #[error("{path:?} has conflicts in copy id\n{summary}", summary = summary.trim_end())]
+    CopyIdConflict { path: RepoPathBuf, summary: String },
     #[error(
         "The output file is either unchanged or empty after the editor quit (run with --debug to \
          see the exact invocation)."
@@ -357,6 +359,12 @@ impl MergeToolFile {
                 summary: conflict.describe(),
             });
         }
+        if file.copy_id.is_none() {
+            return Err(ConflictResolveError::CopyIdConflict {
+                path: repo_path.to_owned(),
+                summary: conflict.describe(),
+            });
+        }
         Ok(Self {
             repo_path: repo_path.to_owned(),
             conflict,
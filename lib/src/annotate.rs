@@ -446,14 +446,14 @@ async fn get_file_contents(
         MaterializedTreeValue::File(mut file) => Ok(file.read_all(path).await?.into()),
         MaterializedTreeValue::FileConflict(file) => {
             // TODO: track line origins without materializing
-            let options = ConflictMaterializeOptions {
-                marker_style: ConflictMarkerStyle::Diff,
-                marker_len: None,
-                merge: MergeOptions {
+            let options = ConflictMaterializeOptions::new(
+                ConflictMarkerStyle::Diff,
+                MergeOptions {
                     hunk_level: FileMergeHunkLevel::Line,
                     same_change: SameChange::Accept,
+                    ignore_trailing_newline: false,
                 },
-            };
+            );
             Ok(materialize_merge_result_to_bytes(&file.contents, &options))
         }
         _ => Ok(BString::default()),
@@ -14,11 +14,28 @@
 
 #![expect(missing_docs)]
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::Hash as _;
+use std::hash::Hasher;
 use std::io;
 use std::io::Write;
+use std::iter;
 use std::iter::zip;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
 use std::pin::Pin;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
 
+use bstr::BStr;
 use bstr::BString;
 use bstr::ByteSlice as _;
 use futures::Stream;
@@ -27,6 +44,7 @@ use futures::stream::BoxStream;
 use futures::try_join;
 use itertools::Itertools as _;
 use pollster::FutureExt as _;
+use thiserror::Error;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncReadExt as _;
 
@@ -38,17 +56,24 @@ use crate::backend::FileId;
 use crate::backend::SymlinkId;
 use crate::backend::TreeId;
 use crate::backend::TreeValue;
+use crate::content_hash::blake2b_hash;
 use crate::copies::CopiesTreeDiffEntry;
 use crate::copies::CopiesTreeDiffEntryPath;
+use crate::diff::CompareBytes;
 use crate::diff::ContentDiff;
 use crate::diff::DiffHunk;
 use crate::diff::DiffHunkKind;
+use crate::diff::find_line_ranges;
 use crate::files;
+use crate::files::FileMergeHunkLevel;
 use crate::files::MergeResult;
+use crate::hex_util::encode_hex;
 use crate::merge::Merge;
 use crate::merge::MergedTreeValue;
 use crate::merge::SameChange;
+use crate::object_id::ObjectId as _;
 use crate::repo_path::RepoPath;
+use crate::repo_path::RepoPathBuf;
 use crate::store::Store;
 use crate::tree_merge::MergeOptions;
 
@@ -64,19 +89,41 @@ const CONFLICT_MARKER_LEN_INCREMENT: usize = 4;
 /// Comment for missing terminating newline in a term of a conflict.
 const NO_EOL_COMMENT: &str = " (no terminating newline)";
 
+/// Header of the manifest written with [`ConflictAnnotations::manifest`].
+const MANIFEST_HEADER: &str = "Conflict manifest";
+
+/// Prefix of the comment lines quoting the content of the bases of a conflict,
+/// as written with [`ConflictAnnotations::base_block`].
+const BASE_BLOCK_LINE_PREFIX: &str = "|";
+
+/// Labels of the comment lines attributing the changes of a 2-sided conflict
+/// to the base and the sides, as written with
+/// [`ConflictAnnotations::attribution`].
+const ATTRIBUTION_LABELS: [&str; 3] = ["base:", "local:", "remote:"];
+
 /// Comment for missing terminating newline in the "add" side of a diff.
 const ADD_NO_EOL_COMMENT: &str = " (removes terminating newline)";
 
 /// Comment for missing terminating newline in the "remove" side of a diff.
 const REMOVE_NO_EOL_COMMENT: &str = " (adds terminating newline)";
 
-fn write_diff_hunks(hunks: &[DiffHunk], file: &mut dyn Write) -> io::Result<()> {
+fn write_diff_hunks(
+    hunks: &[DiffHunk],
+    unprefixed_context: bool,
+    file: &mut dyn Write,
+) -> io::Result<()> {
     for hunk in hunks {
         match hunk.kind {
             DiffHunkKind::Matching => {
-                debug_assert!(hunk.contents.iter().all_equal());
-                for line in hunk.contents[0].lines_with_terminator() {
-                    file.write_all(b" ")?;
+                // The contents may differ in line endings if they were compared
+                // with `CompareBytesIgnoreLineEnding`, in which case the lines
+                // of the "add" side are written.
+                for line in hunk.contents[1].lines_with_terminator() {
+                    // Lines which could be mistaken for diff lines still need a
+                    // prefix
+                    if !unprefixed_context || matches!(line.first(), Some(b' ' | b'-' | b'+')) {
+                        file.write_all(b" ")?;
+                    }
                     write_and_ensure_newline(file, line)?;
                 }
             }
@@ -95,6 +142,48 @@ fn write_diff_hunks(hunks: &[DiffHunk], file: &mut dyn Write) -> io::Result<()>
     Ok(())
 }
 
+/// Compares lines ignoring the difference between CRLF and LF line endings.
+struct CompareBytesIgnoreLineEnding;
+
+impl CompareBytesIgnoreLineEnding {
+    fn normalize(line: &[u8]) -> &[u8] {
+        line.strip_suffix(b"\r\n")
+            .or_else(|| line.strip_suffix(b"\n"))
+            .unwrap_or(line)
+    }
+}
+
+impl CompareBytes for CompareBytesIgnoreLineEnding {
+    fn eq(&self, left: &[u8], right: &[u8]) -> bool {
+        Self::normalize(left) == Self::normalize(right)
+            && left.ends_with(b"\n") == right.ends_with(b"\n")
+    }
+
+    fn hash<H: Hasher>(&self, text: &[u8], state: &mut H) {
+        Self::normalize(text).hash(state);
+        text.ends_with(b"\n").hash(state);
+    }
+}
+
+/// Compares `left` and `right` line by line.
+fn diff_lines<'input>(
+    left: &'input [u8],
+    right: &'input [u8],
+    ignore_line_endings: bool,
+) -> Vec<DiffHunk<'input>> {
+    if ignore_line_endings {
+        ContentDiff::for_tokenizer(
+            [left, right],
+            find_line_ranges,
+            CompareBytesIgnoreLineEnding,
+        )
+        .hunks()
+        .collect()
+    } else {
+        ContentDiff::by_line([left, right]).hunks().collect()
+    }
+}
+
 async fn get_file_contents(
     store: &Store,
     path: &RepoPath,
@@ -130,6 +219,55 @@ pub async fn extract_as_single_hunk(
         .await
 }
 
+/// Estimates the peak memory in bytes needed to materialize `conflict`, so the
+/// caller can decide whether to materialize it or to stream it instead.
+///
+/// Materializing holds the contents of all terms plus the output. Since each
+/// line of a diff gets a prefix, the output is at most twice the size of the
+/// terms, so the estimate is three times the total size of the terms. The
+/// actual usage (the contents of the terms plus the output) is between a third
+/// of the estimate and the estimate. Conflict markers aren't accounted for, so
+/// the estimate may be exceeded by conflicts with many small hunks.
+///
+/// The contents are read without holding them in memory.
+pub async fn estimate_materialize_memory(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+) -> BackendResult<usize> {
+    let mut total_size = 0;
+    for id in conflict.iter().flatten() {
+        let mut reader = store.read_file(path, id).await?;
+        let size = tokio::io::copy(&mut reader, &mut tokio::io::sink())
+            .await
+            .map_err(|err| BackendError::ReadFile {
+                path: path.to_owned(),
+                id: id.clone(),
+                source: err.into(),
+            })?;
+        total_size += usize::try_from(size).unwrap();
+    }
+    Ok(total_size.saturating_mul(3))
+}
+
+/// Returns the shortest conflict marker length (at least
+/// [`MIN_CONFLICT_MARKER_LEN`]) which doesn't collide with any line in the
+/// terms of `conflict` which looks like a conflict marker, so that the
+/// materialized conflict can be parsed back without ambiguity. When
+/// [`ConflictMaterializeOptions::marker_len`] isn't set, slightly longer
+/// markers are chosen by [`choose_materialized_conflict_marker_len()`] so that
+/// they stand out from such lines.
+pub async fn required_marker_length(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+) -> BackendResult<usize> {
+    let contents = extract_as_single_hunk(conflict, store, path).await?;
+    Ok(max_existing_marker_len(&contents)
+        .saturating_add(1)
+        .max(MIN_CONFLICT_MARKER_LEN))
+}
+
 /// A type similar to `MergedTreeValue` but with associated data to include in
 /// e.g. the working copy or in a diff.
 pub enum MaterializedTreeValue {
@@ -251,20 +389,23 @@ pub async fn try_materialize_file_conflict_value(
     path: &RepoPath,
     conflict: &MergedTreeValue,
 ) -> BackendResult<Option<MaterializedFileConflictValue>> {
-    let (Some(unsimplified_ids), Some(executable_bits)) =
-        (conflict.to_file_merge(), conflict.to_executable_merge())
-    else {
+    let (Some(unsimplified_ids), Some(executable_bits), Some(copy_ids)) = (
+        conflict.to_file_merge(),
+        conflict.to_executable_merge(),
+        conflict.to_copy_id_merge(),
+    ) else {
         return Ok(None);
     };
     let ids = unsimplified_ids.simplify();
     let contents = extract_as_single_hunk(&ids, store, path).await?;
     let executable = resolve_file_executable(&executable_bits);
+    let copy_id = resolve_file_copy_id(&copy_ids);
     Ok(Some(MaterializedFileConflictValue {
         unsimplified_ids,
         ids,
         contents,
         executable,
-        copy_id: Some(CopyId::placeholder()),
+        copy_id,
     }))
 }
 
@@ -282,6 +423,109 @@ pub fn resolve_file_executable(merge: &Merge<Option<bool>>) -> Option<bool> {
     }
 }
 
+/// Resolves conflicts in file copy id, returns the original copy id if the file
+/// is deleted and the copy id is unchanged. Returns `None` if the sides
+/// disagree.
+pub fn resolve_file_copy_id(merge: &Merge<Option<CopyId>>) -> Option<CopyId> {
+    let resolved = merge.resolve_trivial(SameChange::Accept).cloned()?;
+    if resolved.is_some() {
+        resolved
+    } else {
+        merge.removes().flatten().cloned().all_equal_value().ok()
+    }
+}
+
+/// Resolves the conflict at `path` by discarding the changes from all sides,
+/// returning the base value. The value is `None` if the path was absent in the
+/// base.
+///
+/// Unlike the other resolution functions, this doesn't take a `Store`, since
+/// the base value is returned as is and no content is read or written.
+///
+/// Returns an error if the conflict doesn't have exactly one base after
+/// simplification, since there would be no single value to revert to.
+pub fn resolve_to_base(
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+) -> BackendResult<Option<TreeValue>> {
+    let simplified = conflict.simplify();
+    if simplified.removes().len() != 1 {
+        return Err(BackendError::Other(
+            format!(
+                "Conflict at {} has {} bases after simplification, so it can't be resolved to \
+                 its base",
+                path.as_internal_file_string(),
+                simplified.removes().len()
+            )
+            .into(),
+        ));
+    }
+    Ok(simplified.get_remove(0).unwrap().clone())
+}
+
+/// Returns the removes and adds of a conflict which are files. Absent terms
+/// are omitted. The parts are in the order of the terms of the conflict.
+pub fn file_parts(conflict: &MergedTreeValue) -> (Vec<&TreeValue>, Vec<&TreeValue>) {
+    filter_conflict_parts(conflict, |value| matches!(value, TreeValue::File { .. }))
+}
+
+/// Returns the removes and adds of a conflict which aren't files, such as
+/// symlinks and trees. This is the complement of [`file_parts()`], and the
+/// parts are in the same order.
+pub fn non_file_parts(conflict: &MergedTreeValue) -> (Vec<&TreeValue>, Vec<&TreeValue>) {
+    filter_conflict_parts(conflict, |value| !matches!(value, TreeValue::File { .. }))
+}
+
+fn filter_conflict_parts(
+    conflict: &MergedTreeValue,
+    predicate: impl Fn(&TreeValue) -> bool,
+) -> (Vec<&TreeValue>, Vec<&TreeValue>) {
+    let removes = conflict
+        .removes()
+        .flatten()
+        .filter(|value| predicate(value))
+        .collect();
+    let adds = conflict
+        .adds()
+        .flatten()
+        .filter(|value| predicate(value))
+        .collect();
+    (removes, adds)
+}
+
+/// Kind of object referenced by a [`TreeValue`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IdKind {
+    File,
+    Symlink,
+    Tree,
+    GitSubmodule,
+}
+
+/// Returns the ids of all objects referenced by the terms of a conflict,
+/// without duplicates.
+///
+/// The ids of the removes come first, followed by the ids of the adds, each in
+/// the order of the terms of the conflict. An id referenced by several terms
+/// is listed at its first occurrence.
+///
+/// This can be used to prefetch the objects before materializing the
+/// conflict.
+pub fn referenced_ids(conflict: &MergedTreeValue) -> Vec<(IdKind, Vec<u8>)> {
+    conflict
+        .removes()
+        .chain(conflict.adds())
+        .flatten()
+        .map(|value| match value {
+            TreeValue::File { id, .. } => (IdKind::File, id.to_bytes()),
+            TreeValue::Symlink(id) => (IdKind::Symlink, id.to_bytes()),
+            TreeValue::Tree(id) => (IdKind::Tree, id.to_bytes()),
+            TreeValue::GitSubmodule(id) => (IdKind::GitSubmodule, id.to_bytes()),
+        })
+        .unique()
+        .collect()
+}
+
 /// Describes what style should be used when materializing conflicts.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -308,8 +552,223 @@ impl ConflictMarkerStyle {
 #[derive(Clone, Debug)]
 pub struct ConflictMaterializeOptions {
     pub marker_style: ConflictMarkerStyle,
+    /// Function to choose the conflict marker style of each conflict hunk,
+    /// overriding `marker_style`. Since the style of each conflict is detected
+    /// when parsing, a file can mix styles.
+    pub hunk_style: Option<HunkStyleFn>,
     pub marker_len: Option<usize>,
     pub merge: MergeOptions,
+    /// Compare lines ignoring CRLF/LF differences when computing the diffs
+    /// of "diff" style conflicts. Lines which only differ in line endings are
+    /// written as context lines with the line endings of the side. The line
+    /// endings of the base can't be recovered from such lines, so they're
+    /// replaced with the ones of the side when the conflict is parsed.
+    pub diff_ignore_line_endings: bool,
+    /// Write lines shared between a base and a side in "diff" style conflicts
+    /// without the leading space, unless the line starts with ` `, `-`, or `+`.
+    /// Such conflicts must be parsed with
+    /// [`ConflictParseOptions::unprefixed_diff_context`].
+    pub unprefixed_diff_context: bool,
+    pub annotations: ConflictAnnotations,
+    pub heuristics: HeuristicConfig,
+}
+
+impl ConflictMaterializeOptions {
+    /// Creates options to materialize conflicts with the given marker style
+    /// and merge options. Other options are disabled.
+    pub fn new(marker_style: ConflictMarkerStyle, merge: MergeOptions) -> Self {
+        Self {
+            marker_style,
+            hunk_style: None,
+            marker_len: None,
+            merge,
+            diff_ignore_line_endings: false,
+            unprefixed_diff_context: false,
+            annotations: ConflictAnnotations::default(),
+            heuristics: HeuristicConfig::default(),
+        }
+    }
+}
+
+/// Chooses the conflict marker style of a conflict hunk.
+pub type HunkStyleFn = fn(&Merge<BString>) -> ConflictMarkerStyle;
+
+/// Additional information to write alongside materialized conflicts. Unless
+/// noted otherwise, the annotations are written as comment lines starting with
+/// `@@@@@@@`, which are ignored when parsing. Since the length of the conflict
+/// markers is chosen to be longer than any run of `@` in the content, lines of
+/// the content can't be mistaken for comment lines.
+#[derive(Clone, Debug, Default)]
+pub struct ConflictAnnotations {
+    /// Write a footer listing the [`hunk_fingerprint()`] of each conflict hunk
+    /// in order.
+    pub manifest: bool,
+    /// Append a short hash of the original contents of each term to its
+    /// conflict marker (e.g. `+++++++ Contents of side #1 [0123abcd]`). When
+    /// the conflict is updated from the materialized content, the hashes map
+    /// the terms back to their original positions, even if the sections were
+    /// reordered or relabeled. Only JJ-style conflict markers are annotated.
+    pub term_hashes: bool,
+    /// Function to compute a context line to write before each conflict.
+    pub context: Option<ConflictContextFn>,
+    /// Write a block of comment lines quoting the content of the bases before
+    /// each conflict, for troubleshooting.
+    pub base_block: bool,
+    /// Write comment lines before each 2-sided conflict which show each region
+    /// changed by the conflict as it appears in the base (`base:`), side #1
+    /// (`local:`), and side #2 (`remote:`), one after another.
+    pub attribution: bool,
+    /// Append a counter to the start marker of each conflict (e.g.
+    /// `<<<<<<< Conflict 2 of 5 [2/5]`), which makes it easy to jump between
+    /// conflicts by searching for it.
+    pub hunk_counters: bool,
+    /// Text of a comment line to write after the last conflict, to separate it
+    /// from the following content. The text must be a single line.
+    pub trailing_separator: Option<String>,
+    /// Text to write as comment lines at the start of a file with conflicts,
+    /// such as [`DEFAULT_CONFLICT_INSTRUCTIONS`]. Each line of the text is
+    /// written as a separate comment line.
+    pub instructions: Option<String>,
+}
+
+/// Instructions for resolving conflicts for users who aren't familiar with the
+/// conflict markers, which can be written with
+/// [`ConflictAnnotations::instructions`].
+pub const DEFAULT_CONFLICT_INSTRUCTIONS: &str = "\
+This file has conflicts. To resolve a conflict, replace everything from its
+<<<<<<< marker to its >>>>>>> marker with the content you want. Comment
+lines like this one are ignored.";
+
+/// Computes a context line (such as the enclosing function signature) for a
+/// conflict, similar to Git's `xfuncname`. It is called with the file content
+/// preceding the conflict, taking the first side of any preceding conflicts.
+pub type ConflictContextFn = fn(&[u8]) -> Option<Vec<u8>>;
+
+/// Configuration of the heuristic choices made when materializing conflicts,
+/// such as which side of a conflict is written as a snapshot.
+///
+/// The heuristics don't use randomness, so the same inputs and config always
+/// produce the same output.
+#[derive(Clone, Debug, Default)]
+pub struct HeuristicConfig {
+    /// Seed for breaking ties between two sides whose diffs against a base are
+    /// the same size, when choosing which side to write as a snapshot with
+    /// [`DiffBaseStrategy::ByIndex`]. With a seed, the tie is broken by
+    /// hashing the contents of both sides along with the seed. Without one,
+    /// the first side is written as a diff. Other heuristics ignore the seed.
+    pub seed: Option<u64>,
+    /// Minimum ratio of content shared between a base and a side for a
+    /// conflict hunk to be rendered with diffs. Conflict hunks in which a base
+    /// shares less content with both adjacent sides are rendered as snapshots
+    /// since the diffs would mostly remove and add everything.
+    pub min_diff_quality: Option<f64>,
+    /// Minimum ratio of content shared between a side and its base for the
+    /// side to be written as a diff. A side which rewrote the content
+    /// wholesale is written as a snapshot after a snapshot of its base, with a
+    /// note, while the other sides of the conflict hunk are still written as
+    /// diffs.
+    pub min_side_diff_quality: Option<f64>,
+    /// How the bases are paired with the sides for writing diffs.
+    pub diff_base: DiffBaseStrategy,
+}
+
+/// How the bases of a conflict are paired with the sides when writing diffs
+/// in the "diff" conflict marker style.
+///
+/// The parser reads the terms in the order they're written, so the bases are
+/// always paired with the sides in order, skipping the side which is written
+/// as a snapshot. The strategy decides which side that is.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DiffBaseStrategy {
+    /// Diff each base against the side with the same index, unless the next
+    /// side is more similar, in which case the side is written as a snapshot.
+    #[default]
+    ByIndex,
+    /// Write the side at the given index as a snapshot. The last side is used
+    /// if the index is out of range.
+    Fixed(usize),
+    /// Pair the bases with the sides such that the total size of the diffs is
+    /// the smallest.
+    Nearest,
+}
+
+impl HeuristicConfig {
+    /// Returns true if the `first` of two equally good candidates should be
+    /// picked.
+    fn break_tie(&self, first: &[u8], second: &[u8]) -> bool {
+        match self.seed {
+            None => true,
+            Some(seed) => {
+                blake2b_hash(&(seed, first.to_vec())) <= blake2b_hash(&(seed, second.to_vec()))
+            }
+        }
+    }
+
+    /// Returns the index of the side of the conflict `hunk` to write as a
+    /// snapshot, or `None` if it should be decided while writing the diffs.
+    fn snapshot_index(&self, hunk: &Merge<BString>, ignore_line_endings: bool) -> Option<usize> {
+        match self.diff_base {
+            DiffBaseStrategy::ByIndex => None,
+            DiffBaseStrategy::Fixed(index) => Some(index.min(hunk.num_sides() - 1)),
+            DiffBaseStrategy::Nearest => {
+                // Diff sizes of each base to the side with the same index and to
+                // the next side
+                let sizes = hunk
+                    .removes()
+                    .enumerate()
+                    .map(|(base_index, base)| {
+                        let size = |add_index| {
+                            let side = hunk.get_add(add_index).unwrap();
+                            diff_size(&diff_lines(base, side, ignore_line_endings))
+                        };
+                        (size(base_index), size(base_index + 1))
+                    })
+                    .collect_vec();
+                // If side k is the snapshot, the bases before it are diffed
+                // against the sides with the same index, and the other bases
+                // against the next sides.
+                (0..hunk.num_sides()).min_by_key(|&index| {
+                    let (before, after) = sizes.split_at(index);
+                    before.iter().map(|(same, _)| same).sum::<usize>()
+                        + after.iter().map(|(_, next)| next).sum::<usize>()
+                })
+            }
+        }
+    }
+
+    /// Returns true if the diffs of the conflict `hunk` would be of too low
+    /// quality to be useful.
+    fn prefers_snapshot(&self, hunk: &Merge<BString>, ignore_line_endings: bool) -> bool {
+        let Some(min_diff_quality) = self.min_diff_quality else {
+            return false;
+        };
+        hunk.removes().enumerate().any(|(base_index, base)| {
+            // A base is shown as a diff to either of its adjacent sides
+            let quality = [base_index, base_index + 1]
+                .into_iter()
+                .filter_map(|add_index| hunk.get_add(add_index))
+                .map(|side| diff_quality(&diff_lines(base, side, ignore_line_endings)))
+                .fold(0.0, f64::max);
+            quality < min_diff_quality
+        })
+    }
+}
+
+/// Returns the ratio of content in both inputs of the diff which is shared.
+/// Empty inputs are considered to be entirely shared.
+fn diff_quality(hunks: &[DiffHunk]) -> f64 {
+    let (shared, total) = hunks.iter().fold((0, 0), |(shared, total), hunk| {
+        let len: usize = hunk.contents.iter().map(|content| content.len()).sum();
+        match hunk.kind {
+            DiffHunkKind::Matching => (shared + len, total + len),
+            DiffHunkKind::Different => (shared, total + len),
+        }
+    });
+    if total == 0 {
+        1.0
+    } else {
+        shared as f64 / total as f64
+    }
 }
 
 /// Characters which can be repeated to form a conflict marker line when
@@ -324,6 +783,7 @@ enum ConflictMarkerLineChar {
     Diff = b'%',
     GitAncestor = b'|',
     GitSeparator = b'=',
+    Comment = b'@',
 }
 
 impl ConflictMarkerLineChar {
@@ -342,6 +802,7 @@ impl ConflictMarkerLineChar {
             b'%' => Some(Self::Diff),
             b'|' => Some(Self::GitAncestor),
             b'=' => Some(Self::GitSeparator),
+            b'@' => Some(Self::Comment),
             _ => None,
         }
     }
@@ -370,6 +831,11 @@ fn write_conflict_marker(
     }
 }
 
+/// Write a comment line, which is ignored when parsing conflicts.
+fn write_comment_line(output: &mut dyn Write, len: usize, text: &str) -> io::Result<()> {
+    write_conflict_marker(output, ConflictMarkerLineChar::Comment, len, text)
+}
+
 /// Parse a conflict marker from a line of a file. The conflict marker may have
 /// any length (even less than MIN_CONFLICT_MARKER_LEN).
 fn parse_conflict_marker_any_len(line: &[u8]) -> Option<ConflictMarkerLine> {
@@ -398,17 +864,72 @@ fn parse_conflict_marker(line: &[u8], expected_len: usize) -> Option<ConflictMar
 /// Given a Merge of files, choose the conflict marker length to use when
 /// materializing conflicts.
 pub fn choose_materialized_conflict_marker_len<T: AsRef<[u8]>>(single_hunk: &Merge<T>) -> usize {
-    let max_existing_marker_len = single_hunk
+    max_existing_marker_len(single_hunk)
+        .saturating_add(CONFLICT_MARKER_LEN_INCREMENT)
+        .max(MIN_CONFLICT_MARKER_LEN)
+}
+
+/// Returns the length of the longest line in `single_hunk` which looks like a
+/// conflict marker, or 0 if there is none.
+fn max_existing_marker_len<T: AsRef<[u8]>>(single_hunk: &Merge<T>) -> usize {
+    single_hunk
         .iter()
         .flat_map(|file| file.as_ref().lines_with_terminator())
         .filter_map(parse_conflict_marker_any_len)
         .map(|marker| marker.len)
         .max()
-        .unwrap_or_default();
+        .unwrap_or_default()
+}
 
-    max_existing_marker_len
-        .saturating_add(CONFLICT_MARKER_LEN_INCREMENT)
-        .max(MIN_CONFLICT_MARKER_LEN)
+/// Returns the conflict marker length to materialize `single_hunk` with,
+/// which is [`ConflictMaterializeOptions::marker_len`] if set.
+fn materialized_conflict_marker_len<T: AsRef<[u8]>>(
+    single_hunk: &Merge<T>,
+    options: &ConflictMaterializeOptions,
+) -> usize {
+    options
+        .marker_len
+        .unwrap_or_else(|| choose_materialized_conflict_marker_len(single_hunk))
+}
+
+/// Result of [`merge_and_materialize_hunks()`].
+enum MaterializedHunks {
+    Resolved(BString),
+    Conflict {
+        hunks: Vec<Merge<BString>>,
+        marker_len: usize,
+        content: BString,
+    },
+}
+
+/// Merges `single_hunk` and, if it doesn't resolve, materializes the conflict
+/// hunks into a buffer. The time taken to write each conflict hunk is recorded
+/// in `timings` if given.
+fn merge_and_materialize_hunks<T: AsRef<[u8]>>(
+    single_hunk: &Merge<T>,
+    options: &ConflictMaterializeOptions,
+    timings: Option<&mut Vec<Duration>>,
+) -> MaterializedHunks {
+    match files::merge_hunks(single_hunk, &options.merge) {
+        MergeResult::Resolved(content) => MaterializedHunks::Resolved(content),
+        MergeResult::Conflict(hunks) => {
+            let marker_len = materialized_conflict_marker_len(single_hunk, options);
+            let mut output = Vec::new();
+            materialize_conflict_hunks_with_timings(
+                &hunks,
+                options,
+                marker_len,
+                timings,
+                &mut output,
+            )
+            .expect("writing to an in-memory buffer should never fail");
+            MaterializedHunks::Conflict {
+                hunks,
+                marker_len,
+                content: output.into(),
+            }
+        }
+    }
 }
 
 pub fn materialize_merge_result<T: AsRef<[u8]>>(
@@ -420,10 +941,8 @@ pub fn materialize_merge_result<T: AsRef<[u8]>>(
     match &merge_result {
         MergeResult::Resolved(content) => output.write_all(content),
         MergeResult::Conflict(hunks) => {
-            let marker_len = options
-                .marker_len
-                .unwrap_or_else(|| choose_materialized_conflict_marker_len(single_hunk));
-            materialize_conflict_hunks(hunks, options.marker_style, marker_len, output)
+            let marker_len = materialized_conflict_marker_len(single_hunk, options);
+            materialize_conflict_hunks(hunks, options, marker_len, output)
         }
     }
 }
@@ -431,40 +950,520 @@ pub fn materialize_merge_result<T: AsRef<[u8]>>(
 pub fn materialize_merge_result_to_bytes<T: AsRef<[u8]>>(
     single_hunk: &Merge<T>,
     options: &ConflictMaterializeOptions,
+) -> BString {
+    match merge_and_materialize_hunks(single_hunk, options, None) {
+        MaterializedHunks::Resolved(content) => content,
+        MaterializedHunks::Conflict { content, .. } => content,
+    }
+}
+
+/// Materializes the complete version of the file on each side of the conflict,
+/// for tools which compare whole files. Unlike the sides written between
+/// conflict markers, each version includes the resolved regions of the file.
+///
+/// Each version is preceded by a header line consisting of a `=` conflict
+/// marker followed by `Contents of side #N`, where the marker is long enough
+/// not to appear in the contents. If a version has no terminating newline, one
+/// is added and the header notes it.
+pub fn materialize_full_versions<T: AsRef<[u8]>>(
+    single_hunk: &Merge<T>,
+    options: &ConflictMaterializeOptions,
+) -> BString {
+    let hunks = match files::merge_hunks(single_hunk, &options.merge) {
+        MergeResult::Resolved(content) => vec![Merge::resolved(content)],
+        MergeResult::Conflict(hunks) => hunks,
+    };
+    let marker_len = materialized_conflict_marker_len(single_hunk, options);
+    let mut output = Vec::new();
+    for add_index in 0..single_hunk.num_sides() {
+        let mut version = BString::new(vec![]);
+        for hunk in &hunks {
+            let content = match hunk.as_resolved() {
+                Some(content) => content,
+                None => hunk.get_add(add_index).unwrap(),
+            };
+            version.extend_from_slice(content);
+        }
+        write_conflict_marker(
+            &mut output,
+            ConflictMarkerLineChar::GitSeparator,
+            marker_len,
+            &format!(
+                "Contents of side #{}{}",
+                add_index + 1,
+                maybe_no_eol_comment(&version)
+            ),
+        )
+        .and_then(|()| write_and_ensure_newline(&mut output, &version))
+        .expect("writing to an in-memory buffer should never fail");
+    }
+    output.into()
+}
+
+/// Formats materialized conflict `content` as a YAML literal block scalar, so it
+/// can be embedded in a structured document after a key at the start of a
+/// line (e.g. `key: |`). Each line is indented by `indent` spaces, so that the
+/// conflict markers don't break the surrounding document. Since YAML only
+/// allows indentation indicators from 1 to 9, `indent` is clamped to that
+/// range. Returns the scalar starting with its header, which chooses how the
+/// final newlines are kept.
+///
+/// Use [`parse_yaml_block_scalar()`] to recover the original content.
+pub fn to_yaml_block_scalar(content: &[u8], indent: usize) -> BString {
+    let indent = indent.clamp(1, 9);
+    let mut output = BString::from("|");
+    // The indentation can't be detected if the first line starts with a space
+    if content
+        .lines_with_terminator()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| line.starts_with(b" "))
+    {
+        output.extend_from_slice(indent.to_string().as_bytes());
+    }
+    if !content.ends_with(b"\n") {
+        output.push(b'-');
+    } else if content.ends_with(b"\n\n") || content.trim_end_with(|c| c == '\n').is_empty() {
+        // Clipping would remove all but one final newline, or all of them if
+        // the content is only newlines
+        output.push(b'+');
+    }
+    output.push(b'\n');
+    for line in content.lines_with_terminator() {
+        if line == b"\n" || line == b"\r\n" {
+            output.extend_from_slice(line);
+        } else {
+            output.extend(iter::repeat_n(b' ', indent));
+            output.extend_from_slice(line);
+        }
+    }
+    if has_no_eol(content) {
+        output.push(b'\n');
+    }
+    output
+}
+
+/// Parses a YAML literal block scalar written by [`to_yaml_block_scalar()`]
+/// with the same `indent`. Returns `None` if the scalar is malformed.
+pub fn parse_yaml_block_scalar(scalar: &[u8], indent: usize) -> Option<BString> {
+    let indent = indent.clamp(1, 9);
+    let mut lines = scalar.lines_with_terminator();
+    let header = lines.next()?.trim_end().strip_prefix(b"|")?;
+    let chomping = header.iter().find(|&&b| b == b'-' || b == b'+').copied();
+    let mut content = BString::new(vec![]);
+    for line in lines {
+        if line == b"\n" || line == b"\r\n" {
+            content.extend_from_slice(line);
+        } else {
+            let rest = line.strip_prefix(vec![b' '; indent].as_slice())?;
+            content.extend_from_slice(rest);
+        }
+    }
+    let trimmed_len = content.trim_end_with(|c| c == '\n').len();
+    match chomping {
+        Some(b'+') => {}
+        Some(_) => content.truncate(trimmed_len),
+        None => {
+            content.truncate(trimmed_len);
+            if !content.is_empty() {
+                content.push(b'\n');
+            }
+        }
+    }
+    Some(content)
+}
+
+/// Like [`materialize_merge_result_to_bytes()`], but also returns the time
+/// taken to write each conflict hunk, which is mostly spent computing diffs.
+/// This can be used to find conflicts which are slow to materialize. The
+/// output is the same as without timings.
+pub fn materialize_merge_result_with_timings<T: AsRef<[u8]>>(
+    single_hunk: &Merge<T>,
+    options: &ConflictMaterializeOptions,
+) -> (BString, Vec<Duration>) {
+    let mut timings = vec![];
+    match merge_and_materialize_hunks(single_hunk, options, Some(&mut timings)) {
+        MaterializedHunks::Resolved(content) => (content, timings),
+        MaterializedHunks::Conflict { content, .. } => (content, timings),
+    }
+}
+
+/// Materializes `single_hunk` for display in a terminal which is `width`
+/// columns wide, with the conflicting sides of each conflict laid out in two
+/// columns next to each other. Lines which match between the sides are
+/// aligned in the same row and separated by `|`, and rows which differ are
+/// separated by `*`. Conflicts with more than two sides are shown as side #1
+/// next to each of the other sides in turn. Lines which don't fit in a column
+/// are truncated and end with `>`.
+///
+/// The output is only meant for display and can't be parsed back.
+pub fn materialize_conflict_side_by_side<T: AsRef<[u8]>>(
+    single_hunk: &Merge<T>,
+    options: &ConflictMaterializeOptions,
+    width: usize,
 ) -> BString {
     let merge_result = files::merge_hunks(single_hunk, &options.merge);
-    match merge_result {
-        MergeResult::Resolved(content) => content,
-        MergeResult::Conflict(hunks) => {
-            let marker_len = options
-                .marker_len
-                .unwrap_or_else(|| choose_materialized_conflict_marker_len(single_hunk));
-            let mut output = Vec::new();
-            materialize_conflict_hunks(&hunks, options.marker_style, marker_len, &mut output)
-                .expect("writing to an in-memory buffer should never fail");
-            output.into()
+    let hunks = match merge_result {
+        MergeResult::Resolved(content) => return content,
+        MergeResult::Conflict(hunks) => hunks,
+    };
+    let marker_len = materialized_conflict_marker_len(single_hunk, options);
+    let column_width = (width.saturating_sub(3) / 2).max(1);
+    let mut output = Vec::new();
+    let write_row = |output: &mut Vec<u8>, left: &[u8], separator: u8, right: &[u8]| {
+        write_side_by_side_column(output, left, column_width);
+        output.extend_from_slice(&[b' ', separator, b' ']);
+        write_side_by_side_column(output, right, column_width);
+        // Drop the padding of the right column
+        let trimmed_len = output.trim_end_with(|c| c == ' ').len();
+        output.truncate(trimmed_len);
+        output.push(b'\n');
+    };
+    let num_conflicts = hunks.iter().filter(|hunk| !hunk.is_resolved()).count();
+    let mut conflict_index = 0;
+    for hunk in &hunks {
+        if let Some(content) = hunk.as_resolved() {
+            for line in content.lines_with_terminator() {
+                write_row(&mut output, line, b'|', line);
+            }
+            continue;
+        }
+        conflict_index += 1;
+        let conflict_info = format!("Conflict {conflict_index} of {num_conflicts}");
+        write_conflict_marker(
+            &mut output,
+            ConflictMarkerLineChar::ConflictStart,
+            marker_len,
+            &conflict_info,
+        )
+        .expect("writing to an in-memory buffer should never fail");
+        let mut sides = hunk.adds();
+        let first_side = sides.next().unwrap();
+        for (side_index, other_side) in zip(1.., sides) {
+            write_row(
+                &mut output,
+                b"side #1",
+                b'|',
+                format!("side #{}", side_index + 1).as_bytes(),
+            );
+            for diff_hunk in diff_lines(first_side, other_side, false) {
+                match diff_hunk.kind {
+                    DiffHunkKind::Matching => {
+                        for line in diff_hunk.contents[0].lines_with_terminator() {
+                            write_row(&mut output, line, b'|', line);
+                        }
+                    }
+                    DiffHunkKind::Different => {
+                        let left_lines = diff_hunk.contents[0].lines_with_terminator();
+                        let right_lines = diff_hunk.contents[1].lines_with_terminator();
+                        for lines in left_lines.zip_longest(right_lines) {
+                            let (left, right) = lines.or(b"", b"");
+                            write_row(&mut output, left, b'*', right);
+                        }
+                    }
+                }
+            }
+        }
+        write_conflict_marker(
+            &mut output,
+            ConflictMarkerLineChar::ConflictEnd,
+            marker_len,
+            &format!("{conflict_info} ends"),
+        )
+        .expect("writing to an in-memory buffer should never fail");
+    }
+    output.into()
+}
+
+/// Writes `line` without its terminator into a column which is `width`
+/// characters wide, truncating or padding it as needed.
+fn write_side_by_side_column(output: &mut Vec<u8>, line: &[u8], width: usize) {
+    let text = line
+        .trim_end_with(|c| c == '\n' || c == '\r')
+        .to_str_lossy();
+    let num_chars = text.chars().count();
+    if num_chars > width {
+        output.extend(text.chars().take(width - 1).collect::<String>().bytes());
+        output.push(b'>');
+    } else {
+        output.extend_from_slice(text.as_bytes());
+        output.resize(output.len() + width - num_chars, b' ');
+    }
+}
+
+/// Merge result materialized along with information about its conflict hunks.
+#[derive(Clone, Debug)]
+pub struct MaterializedMergeResult {
+    /// Materialized content, same as `materialize_merge_result_to_bytes()`.
+    pub content: BString,
+    /// Conflict hunks in the order they appear in the content.
+    pub conflicts: Vec<MaterializedConflictHunk>,
+}
+
+/// Conflict hunk in a [`MaterializedMergeResult`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MaterializedConflictHunk {
+    /// Fingerprint of the hunk as returned by `hunk_fingerprint()`.
+    pub fingerprint: String,
+    /// Whether the hunk is identical to the preceding conflict hunk, in which
+    /// case both can be resolved the same way.
+    pub same_as_previous: bool,
+}
+
+/// Like `materialize_merge_result_to_bytes()`, but also returns information
+/// about each conflict hunk.
+pub fn materialize_merge_result_structured<T: AsRef<[u8]>>(
+    single_hunk: &Merge<T>,
+    options: &ConflictMaterializeOptions,
+) -> MaterializedMergeResult {
+    match merge_and_materialize_hunks(single_hunk, options, None) {
+        MaterializedHunks::Resolved(content) => MaterializedMergeResult {
+            content,
+            conflicts: vec![],
+        },
+        MaterializedHunks::Conflict { hunks, content, .. } => {
+            let mut previous_fingerprint = None;
+            let conflicts = hunks
+                .iter()
+                .filter(|hunk| !hunk.is_resolved())
+                .map(|hunk| {
+                    let fingerprint = hunk_fingerprint(hunk);
+                    let same_as_previous = previous_fingerprint.as_ref() == Some(&fingerprint);
+                    previous_fingerprint = Some(fingerprint.clone());
+                    MaterializedConflictHunk {
+                        fingerprint,
+                        same_as_previous,
+                    }
+                })
+                .collect();
+            MaterializedMergeResult { content, conflicts }
+        }
+    }
+}
+
+/// Reads the contents of a file conflict, merges it, and materializes the
+/// merge result. Both the merge result and the materialized content are
+/// returned so that the merge only has to be computed once.
+pub async fn merge_and_materialize(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    options: &ConflictMaterializeOptions,
+) -> BackendResult<(MergeResult, Vec<u8>)> {
+    let single_hunk = extract_as_single_hunk(conflict, store, path).await?;
+    let result = match merge_and_materialize_hunks(&single_hunk, options, None) {
+        MaterializedHunks::Resolved(content) => {
+            (MergeResult::Resolved(content.clone()), content.into())
+        }
+        MaterializedHunks::Conflict { hunks, content, .. } => {
+            (MergeResult::Conflict(hunks), content.into())
+        }
+    };
+    Ok(result)
+}
+
+/// Materializes a file conflict into a buffer, e.g. to restore the conflict
+/// markers after the conflict was resolved.
+///
+/// This is the inverse of resolving the conflict by `update_from_content()`.
+pub async fn restore_conflict_materialization(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    options: &ConflictMaterializeOptions,
+) -> BackendResult<Vec<u8>> {
+    let (_, content) = merge_and_materialize(store, path, conflict, options).await?;
+    Ok(content)
+}
+
+/// Checks that materializing the file conflict, parsing the result, and
+/// materializing the parsed conflict again produces the same content.
+///
+/// Returns an error describing the first differing line if it doesn't. This
+/// is meant to be used in tests.
+pub async fn assert_roundtrip_stable(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    options: &ConflictMaterializeOptions,
+) -> BackendResult<()> {
+    let roundtrip_error = |message: String| {
+        BackendError::Other(
+            format!(
+                "Materialized conflict at {} isn't stable: {message}",
+                path.as_internal_file_string()
+            )
+            .into(),
+        )
+    };
+    let simplified = conflict.simplify();
+    let single_hunk = extract_as_single_hunk(&simplified, store, path).await?;
+    let MaterializedHunks::Conflict {
+        marker_len,
+        content,
+        ..
+    } = merge_and_materialize_hunks(&single_hunk, options, None)
+    else {
+        // Resolved content doesn't contain conflict markers to parse
+        return Ok(());
+    };
+    let parse_options = ConflictParseOptions {
+        unprefixed_diff_context: options.unprefixed_diff_context,
+    };
+    let Some(mut hunks) =
+        parse_conflict_with_options(&content, simplified.num_sides(), marker_len, &parse_options)
+    else {
+        return Err(roundtrip_error("no conflicts could be parsed".to_owned()));
+    };
+    remove_materialized_eols(&mut hunks, &single_hunk);
+    let mut new_content = Vec::new();
+    materialize_conflict_hunks(&hunks, options, marker_len, &mut new_content)
+        .expect("writing to an in-memory buffer should never fail");
+    if new_content == content {
+        return Ok(());
+    }
+    let (line_number, (old_line, new_line)) = content
+        .lines_with_terminator()
+        .map(Some)
+        .chain(iter::repeat(None))
+        .zip(
+            new_content
+                .lines_with_terminator()
+                .map(Some)
+                .chain(iter::repeat(None)),
+        )
+        .enumerate()
+        .find(|(_, (old_line, new_line))| old_line != new_line)
+        .expect("contents should differ");
+    let describe_line = |line: Option<&[u8]>| match line {
+        Some(line) => format!("{:?}", BStr::new(line)),
+        None => "end of file".to_owned(),
+    };
+    Err(roundtrip_error(format!(
+        "line {} was {} but became {}",
+        line_number + 1,
+        describe_line(old_line),
+        describe_line(new_line)
+    )))
+}
+
+/// How [`materialize_conflict()`] handles conflicts which can't be
+/// represented by conflict markers, such as conflicts involving symlinks.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NonFileConflictMode {
+    /// Write a description of the conflict instead.
+    #[default]
+    Describe,
+    /// Fail with [`ConflictMaterializeError::NonFileConflict`] without writing
+    /// anything.
+    Strict,
+}
+
+/// Error from [`materialize_conflict()`].
+#[derive(Debug, Error)]
+pub enum ConflictMaterializeError {
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+    #[error("Conflict at {} is not a file conflict", path.as_internal_file_string())]
+    NonFileConflict { path: RepoPathBuf },
+    #[error("Failed to write materialized conflict")]
+    Io(#[from] io::Error),
+}
+
+/// Writes the conflict at `path` to `output` using conflict markers. Other
+/// than file conflicts are handled according to `mode`.
+pub async fn materialize_conflict(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+    options: &ConflictMaterializeOptions,
+    mode: NonFileConflictMode,
+    output: &mut dyn Write,
+) -> Result<(), ConflictMaterializeError> {
+    if let Some(file_ids) = conflict.to_file_merge() {
+        let single_hunk = extract_as_single_hunk(&file_ids, store, path).await?;
+        materialize_merge_result(&single_hunk, output, options)?;
+        return Ok(());
+    }
+    match mode {
+        NonFileConflictMode::Describe => output.write_all(conflict.describe().as_bytes())?,
+        NonFileConflictMode::Strict => {
+            return Err(ConflictMaterializeError::NonFileConflict {
+                path: path.to_owned(),
+            });
         }
     }
+    Ok(())
 }
 
 fn materialize_conflict_hunks(
     hunks: &[Merge<BString>],
-    conflict_marker_style: ConflictMarkerStyle,
+    options: &ConflictMaterializeOptions,
+    conflict_marker_len: usize,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    materialize_conflict_hunks_with_timings(hunks, options, conflict_marker_len, None, output)
+}
+
+/// Like [`materialize_conflict_hunks()`], but also records the time taken to
+/// write each conflict hunk in `timings`.
+fn materialize_conflict_hunks_with_timings(
+    hunks: &[Merge<BString>],
+    options: &ConflictMaterializeOptions,
     conflict_marker_len: usize,
+    mut timings: Option<&mut Vec<Duration>>,
     output: &mut dyn Write,
 ) -> io::Result<()> {
     let num_conflicts = hunks
         .iter()
         .filter(|hunk| hunk.as_resolved().is_none())
         .count();
+    if num_conflicts > 0
+        && let Some(instructions) = &options.annotations.instructions
+    {
+        for line in instructions.lines() {
+            write_comment_line(output, conflict_marker_len, line.trim_end())?;
+        }
+    }
     let mut conflict_index = 0;
+    // Content of the first side up to the current hunk, used to compute the
+    // context of the next conflict
+    let mut content_before = BString::default();
     for hunk in hunks {
         if let Some(content) = hunk.as_resolved() {
             output.write_all(content)?;
         } else {
             conflict_index += 1;
             let conflict_info = format!("Conflict {conflict_index} of {num_conflicts}");
-
+            let start_marker_suffix = if options.annotations.hunk_counters {
+                format!(" [{conflict_index}/{num_conflicts}]")
+            } else {
+                String::new()
+            };
+            if let Some(context) = options
+                .annotations
+                .context
+                .and_then(|context| context(&content_before))
+            {
+                write_comment_line(
+                    output,
+                    conflict_marker_len,
+                    &String::from_utf8_lossy(context.lines().next().unwrap_or_default()),
+                )?;
+            }
+
+            if options.annotations.base_block {
+                write_base_block(hunk, conflict_marker_len, output)?;
+            }
+            if options.annotations.attribution
+                && let [left, base, right] = hunk.as_slice()
+            {
+                write_attribution(left, base, right, conflict_marker_len, output)?;
+            }
+
+            let conflict_marker_style = options
+                .hunk_style
+                .map_or(options.marker_style, |hunk_style| hunk_style(hunk));
+            let start_time = Instant::now();
             match (conflict_marker_style, hunk.as_slice()) {
                 // 2-sided conflicts can use Git-style conflict markers
                 (ConflictMarkerStyle::Git, [left, base, right]) => {
@@ -473,6 +1472,7 @@ fn materialize_conflict_hunks(
                         base,
                         right,
                         &conflict_info,
+                        &start_marker_suffix,
                         conflict_marker_len,
                         output,
                     )?;
@@ -481,22 +1481,236 @@ fn materialize_conflict_hunks(
                     materialize_jj_style_conflict(
                         hunk,
                         &conflict_info,
+                        &start_marker_suffix,
                         conflict_marker_style,
+                        options,
                         conflict_marker_len,
                         output,
                     )?;
                 }
             }
+            if let Some(timings) = timings.as_deref_mut() {
+                timings.push(start_time.elapsed());
+            }
+            if conflict_index == num_conflicts
+                && let Some(separator) = &options.annotations.trailing_separator
+            {
+                write_comment_line(output, conflict_marker_len, separator)?;
+            }
+        }
+        if options.annotations.context.is_some() {
+            content_before.extend_from_slice(hunk.first());
+        }
+    }
+    if options.annotations.manifest {
+        write_conflict_manifest(hunks, conflict_marker_len, output)?;
+    }
+    Ok(())
+}
+
+/// Writes comment lines quoting the content of each base of a conflict hunk.
+fn write_base_block(
+    hunk: &Merge<BString>,
+    conflict_marker_len: usize,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    for (base_index, base) in hunk.removes().enumerate() {
+        let base_str = if hunk.removes().len() == 1 {
+            "base".to_string()
+        } else {
+            format!("base #{}", base_index + 1)
+        };
+        write_comment_line(
+            output,
+            conflict_marker_len,
+            &format!("----- {base_str} -----"),
+        )?;
+        for line in base.lines() {
+            let text = format!("{BASE_BLOCK_LINE_PREFIX} {}", line.to_str_lossy());
+            write_comment_line(output, conflict_marker_len, text.trim_end())?;
+        }
+    }
+    write_comment_line(output, conflict_marker_len, "----- end of base -----")
+}
+
+/// Writes comment lines showing each region which differs between the base
+/// and the sides of a 2-sided conflict hunk, attributed to the term it comes
+/// from.
+fn write_attribution(
+    left: &[u8],
+    base: &[u8],
+    right: &[u8],
+    conflict_marker_len: usize,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let diff = ContentDiff::by_line([base, left, right]);
+    let changes = diff
+        .hunks()
+        .filter(|hunk| hunk.kind == DiffHunkKind::Different)
+        .collect_vec();
+    for (change_index, change) in changes.iter().enumerate() {
+        write_comment_line(
+            output,
+            conflict_marker_len,
+            &format!(
+                "----- change {} of {} -----",
+                change_index + 1,
+                changes.len()
+            ),
+        )?;
+        for (label, content) in zip(ATTRIBUTION_LABELS, &change.contents) {
+            if content.is_empty() {
+                write_comment_line(output, conflict_marker_len, label)?;
+            }
+            for line in content.lines() {
+                let text = format!("{label} {}", line.to_str_lossy());
+                write_comment_line(output, conflict_marker_len, text.trim_end())?;
+            }
         }
     }
+    write_comment_line(output, conflict_marker_len, "----- end of changes -----")
+}
+
+/// Writes a footer listing the fingerprint of each conflict hunk.
+fn write_conflict_manifest(
+    hunks: &[Merge<BString>],
+    conflict_marker_len: usize,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    // If the file ends with unterminated resolved content, a newline has to be
+    // inserted before the footer. The parser removes it again.
+    let no_eol = hunks
+        .last()
+        .and_then(|hunk| hunk.as_resolved())
+        .is_some_and(|content| has_no_eol(content));
+    if no_eol {
+        writeln!(output)?;
+    }
+    let conflicts = hunks
+        .iter()
+        .filter(|hunk| !hunk.is_resolved())
+        .collect_vec();
+    write_comment_line(
+        output,
+        conflict_marker_len,
+        &format!(
+            "{MANIFEST_HEADER}{}",
+            if no_eol { NO_EOL_COMMENT } else { "" }
+        ),
+    )?;
+    for (index, hunk) in conflicts.iter().enumerate() {
+        write_comment_line(
+            output,
+            conflict_marker_len,
+            &format!(
+                "Conflict {} of {}: {}",
+                index + 1,
+                conflicts.len(),
+                hunk_fingerprint(hunk)
+            ),
+        )?;
+    }
     Ok(())
 }
 
+/// Returns a short, stable identifier of the contents of a conflict hunk.
+///
+/// The fingerprint only depends on the contents of the terms, so the same
+/// conflict hunk gets the same fingerprint across sessions.
+pub fn hunk_fingerprint(hunk: &Merge<BString>) -> String {
+    let hash = blake2b_hash(&hunk.map(|term| term.to_vec()));
+    encode_hex(&hash[..6])
+}
+
+/// Returns a short hash of the contents of a term, as written in conflict
+/// markers with [`ConflictAnnotations::term_hashes`].
+fn term_hash(term: &[u8]) -> String {
+    encode_hex(&blake2b_hash(&term.to_vec())[..4])
+}
+
+/// Parses the term hashes at the end of a conflict marker line, as written with
+/// [`ConflictAnnotations::term_hashes`].
+fn parse_term_hashes(line: &[u8]) -> Option<Vec<String>> {
+    let line = line.trim_end().strip_suffix(b"]")?;
+    let start = line.rfind_byte(b'[')?;
+    line[start + 1..]
+        .split_str(" ")
+        .map(|hash| {
+            (hash.len() == 8 && hash.iter().all(u8::is_ascii_hexdigit))
+                .then(|| hash.to_str_lossy().into_owned())
+        })
+        .collect()
+}
+
+/// Parses the term hashes of each term of a JJ-style conflict hunk. Returns
+/// `None` if any of the conflict markers has no (valid) hashes.
+fn parse_conflict_hunk_term_hashes(
+    input: &[u8],
+    expected_marker_len: usize,
+) -> Option<Merge<String>> {
+    let mut removes = vec![];
+    let mut adds = vec![];
+    for line in input.lines_with_terminator() {
+        let Some(kind) = parse_conflict_marker(line, expected_marker_len) else {
+            continue;
+        };
+        match (kind, parse_term_hashes(line)?.as_slice()) {
+            (ConflictMarkerLineChar::Diff, [remove, add]) => {
+                removes.push(remove.clone());
+                adds.push(add.clone());
+            }
+            (ConflictMarkerLineChar::Remove, [remove]) => removes.push(remove.clone()),
+            (ConflictMarkerLineChar::Add, [add]) => adds.push(add.clone()),
+            _ => return None,
+        }
+    }
+    (adds.len() == removes.len() + 1).then(|| Merge::from_removes_adds(removes, adds))
+}
+
+/// Reorders the terms of a parsed conflict `hunk` to match the conflict hunk
+/// in `old_hunks` with the same term hashes. Returns `None` if there's no such
+/// conflict hunk.
+fn remap_terms_by_hash(
+    hunk: &Merge<BString>,
+    hashes: &Merge<String>,
+    old_hunks: &[Merge<BString>],
+) -> Option<Merge<BString>> {
+    let old_hashes = old_hunks
+        .iter()
+        .filter(|old_hunk| !old_hunk.is_resolved())
+        .map(|old_hunk| old_hunk.map(|term| term_hash(term)))
+        .find(|old_hashes| {
+            old_hashes.removes().sorted().eq(hashes.removes().sorted())
+                && old_hashes.adds().sorted().eq(hashes.adds().sorted())
+        })?;
+    fn permute<'a>(
+        old_hashes: impl Iterator<Item = &'a String>,
+        new_hashes: impl Iterator<Item = &'a String>,
+        terms: impl Iterator<Item = &'a BString>,
+    ) -> Vec<BString> {
+        let mut slots = zip(new_hashes, terms).map(Some).collect_vec();
+        old_hashes
+            .map(|old_hash| {
+                let slot = slots
+                    .iter_mut()
+                    .find(|slot| slot.is_some_and(|(new_hash, _)| new_hash == old_hash))
+                    .expect("term hashes should match");
+                slot.take().unwrap().1.clone()
+            })
+            .collect()
+    }
+    Some(Merge::from_removes_adds(
+        permute(old_hashes.removes(), hashes.removes(), hunk.removes()),
+        permute(old_hashes.adds(), hashes.adds(), hunk.adds()),
+    ))
+}
+
 fn materialize_git_style_conflict(
     left: &[u8],
     base: &[u8],
     right: &[u8],
     conflict_info: &str,
+    start_marker_suffix: &str,
     conflict_marker_len: usize,
     output: &mut dyn Write,
 ) -> io::Result<()> {
@@ -504,7 +1718,7 @@ fn materialize_git_style_conflict(
         output,
         ConflictMarkerLineChar::ConflictStart,
         conflict_marker_len,
-        &format!("Side #1 ({conflict_info})"),
+        &format!("Side #1 ({conflict_info}){start_marker_suffix}"),
     )?;
     write_and_ensure_newline(output, left)?;
 
@@ -538,68 +1752,121 @@ fn materialize_git_style_conflict(
 fn materialize_jj_style_conflict(
     hunk: &Merge<BString>,
     conflict_info: &str,
+    start_marker_suffix: &str,
     conflict_marker_style: ConflictMarkerStyle,
+    options: &ConflictMaterializeOptions,
     conflict_marker_len: usize,
     output: &mut dyn Write,
 ) -> io::Result<()> {
+    let ignore_line_endings = options.diff_ignore_line_endings;
+    let conflict_marker_style = if conflict_marker_style.allows_diff()
+        && options
+            .heuristics
+            .prefers_snapshot(hunk, ignore_line_endings)
+    {
+        ConflictMarkerStyle::Snapshot
+    } else {
+        conflict_marker_style
+    };
+    let term_hashes_suffix = |terms: &[&[u8]]| {
+        if options.annotations.term_hashes {
+            format!(" [{}]", terms.iter().map(|term| term_hash(term)).join(" "))
+        } else {
+            String::new()
+        }
+    };
+
     // Write a positive snapshot (side) of a conflict
+    let write_side_with_note =
+        |add_index: usize, data: &[u8], note: &str, output: &mut dyn Write| {
+            write_conflict_marker(
+                output,
+                ConflictMarkerLineChar::Add,
+                conflict_marker_len,
+                &format!(
+                    "Contents of side #{}{note}{}{}",
+                    add_index + 1,
+                    maybe_no_eol_comment(data),
+                    term_hashes_suffix(&[data])
+                ),
+            )?;
+            write_and_ensure_newline(output, data)
+        };
     let write_side = |add_index: usize, data: &[u8], output: &mut dyn Write| {
+        write_side_with_note(add_index, data, "", output)
+    };
+
+    // Write a negative snapshot (base) of a conflict
+    let write_base = |base_str: &str, data: &[u8], output: &mut dyn Write| {
         write_conflict_marker(
             output,
-            ConflictMarkerLineChar::Add,
+            ConflictMarkerLineChar::Remove,
             conflict_marker_len,
             &format!(
-                "Contents of side #{}{}",
-                add_index + 1,
-                maybe_no_eol_comment(data)
+                "Contents of {base_str}{}{}",
+                maybe_no_eol_comment(data),
+                term_hashes_suffix(&[data])
             ),
         )?;
         write_and_ensure_newline(output, data)
     };
 
-    // Write a negative snapshot (base) of a conflict
-    let write_base = |base_str: &str, data: &[u8], output: &mut dyn Write| {
+    // Write a diff from a negative term to a positive term
+    let write_diff = |base_str: &str,
+                      left: &[u8],
+                      add_index: usize,
+                      right: &[u8],
+                      diff: &[DiffHunk],
+                      output: &mut dyn Write| {
+        let no_eol_remove = diff
+            .last()
+            .is_some_and(|diff_hunk| has_no_eol(diff_hunk.contents[0]));
+        let no_eol_add = diff
+            .last()
+            .is_some_and(|diff_hunk| has_no_eol(diff_hunk.contents[1]));
+        let no_eol_comment = match (no_eol_remove, no_eol_add) {
+            (true, true) => NO_EOL_COMMENT,
+            (true, _) => REMOVE_NO_EOL_COMMENT,
+            (_, true) => ADD_NO_EOL_COMMENT,
+            _ => "",
+        };
         write_conflict_marker(
             output,
-            ConflictMarkerLineChar::Remove,
+            ConflictMarkerLineChar::Diff,
             conflict_marker_len,
-            &format!("Contents of {base_str}{}", maybe_no_eol_comment(data)),
+            &format!(
+                "Changes from {base_str} to side #{}{no_eol_comment}{}",
+                add_index + 1,
+                term_hashes_suffix(&[left, right])
+            ),
         )?;
-        write_and_ensure_newline(output, data)
+        write_diff_hunks(diff, options.unprefixed_diff_context, output)
     };
 
-    // Write a diff from a negative term to a positive term
-    let write_diff =
-        |base_str: &str, add_index: usize, diff: &[DiffHunk], output: &mut dyn Write| {
-            let no_eol_remove = diff
-                .last()
-                .is_some_and(|diff_hunk| has_no_eol(diff_hunk.contents[0]));
-            let no_eol_add = diff
-                .last()
-                .is_some_and(|diff_hunk| has_no_eol(diff_hunk.contents[1]));
-            let no_eol_comment = match (no_eol_remove, no_eol_add) {
-                (true, true) => NO_EOL_COMMENT,
-                (true, _) => REMOVE_NO_EOL_COMMENT,
-                (_, true) => ADD_NO_EOL_COMMENT,
-                _ => "",
-            };
-            write_conflict_marker(
-                output,
-                ConflictMarkerLineChar::Diff,
-                conflict_marker_len,
-                &format!(
-                    "Changes from {base_str} to side #{}{no_eol_comment}",
-                    add_index + 1
-                ),
-            )?;
-            write_diff_hunks(diff, output)
-        };
+    // Write a diff, or snapshots if the side rewrote the base wholesale
+    let write_diff_or_snapshots = |base_str: &str,
+                                   left: &[u8],
+                                   add_index: usize,
+                                   right: &[u8],
+                                   diff: &[DiffHunk],
+                                   output: &mut dyn Write| {
+        if options
+            .heuristics
+            .min_side_diff_quality
+            .is_some_and(|min_quality| diff_quality(diff) < min_quality)
+        {
+            write_base(base_str, left, output)?;
+            write_side_with_note(add_index, right, " (rewritten)", output)
+        } else {
+            write_diff(base_str, left, add_index, right, diff, output)
+        }
+    };
 
     write_conflict_marker(
         output,
         ConflictMarkerLineChar::ConflictStart,
         conflict_marker_len,
-        conflict_info,
+        &format!("{conflict_info}{start_marker_suffix}"),
     )?;
     let mut snapshot_written = false;
     // The only conflict marker style which can start with a diff is "diff".
@@ -607,6 +1874,11 @@ fn materialize_jj_style_conflict(
         write_side(0, hunk.first(), output)?;
         snapshot_written = true;
     }
+    let snapshot_index = if snapshot_written {
+        None
+    } else {
+        options.heuristics.snapshot_index(hunk, ignore_line_endings)
+    };
     for (base_index, left) in hunk.removes().enumerate() {
         let add_index = if snapshot_written {
             base_index + 1
@@ -632,24 +1904,32 @@ fn materialize_jj_style_conflict(
             continue;
         }
 
-        let diff1 = ContentDiff::by_line([&left, &right1]).hunks().collect_vec();
+        let diff1 = diff_lines(left, right1, ignore_line_endings);
         // If we haven't written a snapshot yet, then we need to decide whether to
         // format the current side as a snapshot or a diff. We write the current side as
         // a diff unless the next side has a smaller diff compared to the current base.
         if !snapshot_written {
             let right2 = hunk.get_add(add_index + 1).unwrap();
-            let diff2 = ContentDiff::by_line([&left, &right2]).hunks().collect_vec();
-            if diff_size(&diff2) < diff_size(&diff1) {
+            let diff2 = diff_lines(left, right2, ignore_line_endings);
+            let prefer_diff2 = match snapshot_index {
+                Some(snapshot_index) => snapshot_index == add_index,
+                None => match diff_size(&diff2).cmp(&diff_size(&diff1)) {
+                    Ordering::Less => true,
+                    Ordering::Equal => !options.heuristics.break_tie(right1, right2),
+                    Ordering::Greater => false,
+                },
+            };
+            if prefer_diff2 {
                 // If the next positive term is a better match, emit the current positive term
                 // as a snapshot and the next positive term as a diff.
                 write_side(add_index, right1, output)?;
-                write_diff(&base_str, add_index + 1, &diff2, output)?;
+                write_diff_or_snapshots(&base_str, left, add_index + 1, right2, &diff2, output)?;
                 snapshot_written = true;
                 continue;
             }
         }
 
-        write_diff(&base_str, add_index, &diff1, output)?;
+        write_diff_or_snapshots(&base_str, left, add_index, right1, &diff1, output)?;
     }
 
     // If we still didn't emit a snapshot, the last side is the snapshot.
@@ -740,6 +2020,46 @@ pub fn parse_conflict(
     num_sides: usize,
     expected_marker_len: usize,
 ) -> Option<Vec<Merge<BString>>> {
+    parse_conflict_with_options(
+        input,
+        num_sides,
+        expected_marker_len,
+        &ConflictParseOptions::default(),
+    )
+}
+
+/// Options for parsing materialized conflicts. They must match the options
+/// used to materialize the conflicts.
+#[derive(Clone, Debug, Default)]
+pub struct ConflictParseOptions {
+    /// Accept lines without a prefix in diffs as shared lines, as written with
+    /// [`ConflictMaterializeOptions::unprefixed_diff_context`]. A line
+    /// starting with a space has the space removed.
+    pub unprefixed_diff_context: bool,
+}
+
+/// Like [`parse_conflict()`], but with the given `options`.
+pub fn parse_conflict_with_options(
+    input: &[u8],
+    num_sides: usize,
+    expected_marker_len: usize,
+    options: &ConflictParseOptions,
+) -> Option<Vec<Merge<BString>>> {
+    let hunks = parse_conflict_with_term_hashes(input, num_sides, expected_marker_len, options)?;
+    Some(hunks.into_iter().map(|(hunk, _hashes)| hunk).collect())
+}
+
+/// A parsed hunk along with the term hashes written in its conflict markers.
+type HashedHunk = (Merge<BString>, Option<Merge<String>>);
+
+/// Like [`parse_conflict_with_options()`], but also returns the term hashes
+/// written in the conflict markers of each conflict hunk, if any.
+fn parse_conflict_with_term_hashes(
+    input: &[u8],
+    num_sides: usize,
+    expected_marker_len: usize,
+    options: &ConflictParseOptions,
+) -> Option<Vec<HashedHunk>> {
     if input.is_empty() {
         return None;
     }
@@ -757,13 +2077,20 @@ pub fn parse_conflict(
             Some(ConflictMarkerLineChar::ConflictEnd) => {
                 if let Some(conflict_start_index) = conflict_start.take() {
                     let conflict_body = &input[conflict_start_index + conflict_start_len..pos];
-                    let hunk = parse_conflict_hunk(conflict_body, expected_marker_len);
+                    let hunk = parse_conflict_hunk(conflict_body, expected_marker_len, options);
                     if hunk.num_sides() == num_sides {
-                        let resolved_slice = &input[resolved_start..conflict_start_index];
+                        let resolved_slice = strip_comment_lines(
+                            &input[resolved_start..conflict_start_index],
+                            expected_marker_len,
+                        );
                         if !resolved_slice.is_empty() {
-                            hunks.push(Merge::resolved(BString::from(resolved_slice)));
+                            hunks.push((Merge::resolved(resolved_slice.into_owned().into()), None));
                         }
-                        hunks.push(hunk);
+                        let hashes = parse_conflict_hunk_term_hashes(
+                            &strip_comment_lines(conflict_body, expected_marker_len),
+                            expected_marker_len,
+                        );
+                        hunks.push((hunk, hashes));
                         resolved_start = pos + line.len();
                     }
                 }
@@ -776,19 +2103,105 @@ pub fn parse_conflict(
     if hunks.is_empty() {
         None
     } else {
-        if resolved_start < input.len() {
-            hunks.push(Merge::resolved(BString::from(&input[resolved_start..])));
+        let resolved_slice = strip_comment_lines(&input[resolved_start..], expected_marker_len);
+        if !resolved_slice.is_empty() {
+            hunks.push((Merge::resolved(resolved_slice.into_owned().into()), None));
         }
         Some(hunks)
     }
 }
 
+/// Version of the format of materialized conflicts, so that files written by
+/// older versions can still be parsed.
+///
+/// The versions only differ in the length of the conflict markers, so parsing
+/// an older version amounts to falling back to markers of
+/// [`MIN_CONFLICT_MARKER_LEN`] characters when the file has no markers of the
+/// expected length.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictFormatVersion {
+    /// Format written before the length of the conflict markers was chosen per
+    /// file, which is parsed as a fallback with markers of
+    /// [`MIN_CONFLICT_MARKER_LEN`] characters.
+    LegacyMinMarkerLen,
+    /// Current format, in which the conflict markers have at least the
+    /// expected length.
+    Current,
+}
+
+/// Detects the format version of the conflicts in `input` from the length of
+/// their markers. The conflicts are in the current format if they can be parsed
+/// with `expected_marker_len`, and in the legacy format if they can only be
+/// parsed with [`MIN_CONFLICT_MARKER_LEN`]. Returns `None` if the input doesn't
+/// contain conflicts of either version.
+pub fn detect_conflict_format_version(
+    input: &[u8],
+    num_sides: usize,
+    expected_marker_len: usize,
+) -> Option<ConflictFormatVersion> {
+    if parse_conflict(input, num_sides, expected_marker_len).is_some() {
+        Some(ConflictFormatVersion::Current)
+    } else if parse_conflict(input, num_sides, MIN_CONFLICT_MARKER_LEN).is_some() {
+        Some(ConflictFormatVersion::LegacyMinMarkerLen)
+    } else {
+        None
+    }
+}
+
+/// Like [`parse_conflict()`], but parses the conflicts in the given format
+/// `version`, or detects it if `None`.
+pub fn parse_conflict_with_version(
+    input: &[u8],
+    num_sides: usize,
+    expected_marker_len: usize,
+    version: Option<ConflictFormatVersion>,
+) -> Option<Vec<Merge<BString>>> {
+    let version = version
+        .or_else(|| detect_conflict_format_version(input, num_sides, expected_marker_len))?;
+    match version {
+        ConflictFormatVersion::LegacyMinMarkerLen => {
+            parse_conflict(input, num_sides, MIN_CONFLICT_MARKER_LEN)
+        }
+        ConflictFormatVersion::Current => parse_conflict(input, num_sides, expected_marker_len),
+    }
+}
+
+/// Removes comment lines written alongside materialized conflicts. If the
+/// header of the manifest notes that the preceding content has no terminating
+/// newline, the newline which was inserted before it is removed as well.
+fn strip_comment_lines(input: &[u8], expected_marker_len: usize) -> Cow<'_, [u8]> {
+    let is_comment_line = |line: &[u8]| {
+        parse_conflict_marker(line, expected_marker_len) == Some(ConflictMarkerLineChar::Comment)
+    };
+    if !input.lines_with_terminator().any(is_comment_line) {
+        return Cow::Borrowed(input);
+    }
+    let no_eol_manifest_header = format!("{MANIFEST_HEADER}{NO_EOL_COMMENT}");
+    let mut output = vec![];
+    for line in input.lines_with_terminator() {
+        if is_comment_line(line) {
+            let text = line.trim_start_with(|c| c == '@').trim();
+            if text == no_eol_manifest_header.as_bytes() && output.ends_with(b"\n") {
+                output.pop();
+            }
+            continue;
+        }
+        output.extend_from_slice(line);
+    }
+    Cow::Owned(output)
+}
+
 /// This method handles parsing both JJ-style and Git-style conflict markers,
 /// meaning that switching conflict marker styles won't prevent existing files
 /// with other conflict marker styles from being parsed successfully. The
 /// conflict marker style to use for parsing is determined based on the first
 /// line of the hunk.
-fn parse_conflict_hunk(input: &[u8], expected_marker_len: usize) -> Merge<BString> {
+fn parse_conflict_hunk(
+    input: &[u8],
+    expected_marker_len: usize,
+    options: &ConflictParseOptions,
+) -> Merge<BString> {
+    let input = &strip_comment_lines(input, expected_marker_len);
     // If the hunk starts with a conflict marker, find its first character
     let initial_conflict_marker = input
         .lines_with_terminator()
@@ -801,7 +2214,7 @@ fn parse_conflict_hunk(input: &[u8], expected_marker_len: usize) -> Merge<BStrin
             ConflictMarkerLineChar::Diff
             | ConflictMarkerLineChar::Remove
             | ConflictMarkerLineChar::Add,
-        ) => parse_jj_style_conflict_hunk(input, expected_marker_len),
+        ) => parse_jj_style_conflict_hunk(input, expected_marker_len, options),
         // Git-style conflicts either must not start with a conflict marker line, or must start with
         // the "|||||||" conflict marker line (if the first side was empty)
         None | Some(ConflictMarkerLineChar::GitAncestor) => {
@@ -812,7 +2225,11 @@ fn parse_conflict_hunk(input: &[u8], expected_marker_len: usize) -> Merge<BStrin
     }
 }
 
-fn parse_jj_style_conflict_hunk(input: &[u8], expected_marker_len: usize) -> Merge<BString> {
+fn parse_jj_style_conflict_hunk(
+    input: &[u8],
+    expected_marker_len: usize,
+    options: &ConflictParseOptions,
+) -> Merge<BString> {
     enum State {
         Diff,
         Remove,
@@ -857,6 +2274,9 @@ fn parse_jj_style_conflict_hunk(input: &[u8], expected_marker_len: usize) -> Mer
                     // the empty line to the "remove" and "add" as if there was a space in front
                     removes.last_mut().unwrap().extend_from_slice(line);
                     adds.last_mut().unwrap().extend_from_slice(line);
+                } else if options.unprefixed_diff_context {
+                    removes.last_mut().unwrap().extend_from_slice(line);
+                    adds.last_mut().unwrap().extend_from_slice(line);
                 } else {
                     // Doesn't look like a valid conflict
                     return Merge::resolved(BString::new(vec![]));
@@ -931,85 +2351,543 @@ fn parse_git_style_conflict_hunk(input: &[u8], expected_marker_len: usize) -> Me
     }
 }
 
-/// Parses conflict markers in `content` and returns an updated version of
-/// `file_ids` with the new contents. If no (valid) conflict markers remain, a
-/// single resolves `FileId` will be returned.
-pub async fn update_from_content(
-    file_ids: &Merge<Option<FileId>>,
-    store: &Store,
-    path: &RepoPath,
-    content: &[u8],
-    conflict_marker_len: usize,
-) -> BackendResult<Merge<Option<FileId>>> {
-    let simplified_file_ids = file_ids.simplify();
-
-    let old_contents = extract_as_single_hunk(&simplified_file_ids, store, path).await?;
-    let old_hunks = files::merge_hunks(&old_contents, store.merge_options());
+/// Finds the length of the conflict markers in `content`, which is the length
+/// of the first conflict start marker at least [`MIN_CONFLICT_MARKER_LEN`]
+/// long.
+fn detect_conflict_marker_len(content: &[u8]) -> Option<usize> {
+    content
+        .lines_with_terminator()
+        .filter_map(parse_conflict_marker_any_len)
+        .find(|marker| {
+            marker.kind == ConflictMarkerLineChar::ConflictStart
+                && marker.len >= MIN_CONFLICT_MARKER_LEN
+        })
+        .map(|marker| marker.len)
+}
 
-    // Parse conflicts from the new content using the arity of the simplified
-    // conflicts.
-    let mut new_hunks = parse_conflict(
-        content,
-        simplified_file_ids.num_sides(),
-        conflict_marker_len,
-    );
-
-    // If there is a conflict at the end of the file and a term ends with a newline,
-    // check whether the original term ended with a newline. If it didn't, then
-    // remove the newline since it was added automatically when materializing.
-    if let Some(last_hunk) = new_hunks
-        .as_mut()
-        .and_then(|hunks| hunks.last_mut())
-        .filter(|hunk| !hunk.is_resolved())
-    {
-        for (original_content, term) in old_contents.iter().zip_eq(last_hunk.iter_mut()) {
-            if term.last() == Some(&b'\n') && has_no_eol(original_content) {
-                term.pop();
+/// Infers the number of sides from the first well-formed conflict in
+/// `content`.
+fn infer_num_sides(content: &[u8], expected_marker_len: usize) -> Option<usize> {
+    let mut pos = 0;
+    let mut conflict_body_start = None;
+    for line in content.lines_with_terminator() {
+        match parse_conflict_marker(line, expected_marker_len) {
+            Some(ConflictMarkerLineChar::ConflictStart) => {
+                conflict_body_start = Some(pos + line.len());
+            }
+            Some(ConflictMarkerLineChar::ConflictEnd) => {
+                if let Some(start) = conflict_body_start.take() {
+                    let hunk = parse_conflict_hunk(
+                        &content[start..pos],
+                        expected_marker_len,
+                        &ConflictParseOptions::default(),
+                    );
+                    if !hunk.is_resolved() {
+                        return Some(hunk.num_sides());
+                    }
+                }
             }
+            _ => {}
         }
+        pos += line.len();
     }
+    None
+}
 
-    // Check if the new hunks are unchanged. This makes sure that unchanged file
-    // conflicts aren't updated to partially-resolved contents.
-    let unchanged = match (&old_hunks, &new_hunks) {
-        (MergeResult::Resolved(old), None) => old == content,
-        (MergeResult::Conflict(old), Some(new)) => old == new,
-        (MergeResult::Resolved(_), Some(_)) | (MergeResult::Conflict(_), None) => false,
-    };
-    if unchanged {
-        return Ok(file_ids.clone());
+/// Guesses the conflict marker style used to write the conflicts in `content`.
+fn detect_conflict_marker_style(content: &[u8], expected_marker_len: usize) -> ConflictMarkerStyle {
+    let mut style = ConflictMarkerStyle::Snapshot;
+    for line in content.lines_with_terminator() {
+        match parse_conflict_marker(line, expected_marker_len) {
+            Some(ConflictMarkerLineChar::Diff) => return ConflictMarkerStyle::Diff,
+            Some(ConflictMarkerLineChar::GitAncestor) => style = ConflictMarkerStyle::Git,
+            _ => {}
+        }
     }
+    style
+}
 
-    let Some(hunks) = new_hunks else {
-        // Either there are no markers or they don't have the expected arity
-        let file_id = store.write_file(path, &mut &content[..]).await?;
-        return Ok(Merge::normal(file_id));
-    };
+/// Problem with the conflict markers in materialized content, as found by
+/// [`validate_markers()`].
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("{kind} at line {}", self.line + 1)]
+pub struct MarkerError {
+    pub kind: MarkerErrorKind,
+    /// 0-based line number of the marker with the problem.
+    pub line: usize,
+    /// Byte offset of the start of the line.
+    pub offset: usize,
+}
 
-    let mut contents = simplified_file_ids.map(|_| vec![]);
-    for hunk in hunks {
-        if let Some(slice) = hunk.as_resolved() {
-            for content in contents.iter_mut() {
-                content.extend_from_slice(slice);
+/// Kind of a [`MarkerError`].
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub enum MarkerErrorKind {
+    /// A conflict start marker has no matching end marker.
+    #[error("Conflict is never closed")]
+    Unterminated,
+    /// A conflict end marker appears outside of a conflict.
+    #[error("Unexpected conflict end marker")]
+    UnexpectedEnd,
+    /// A conflict start marker appears inside of a conflict.
+    #[error("Unexpected conflict start marker inside a conflict")]
+    NestedStart,
+    /// A conflict has no markers starting a section, such as `+++++++`.
+    #[error("Conflict has no sections")]
+    NoSections,
+}
+
+/// Checks that every conflict start marker in `content` is followed by an end
+/// marker, with at least one section marker in between, and that there are no
+/// other conflict markers outside of conflicts. This is much cheaper than
+/// parsing the conflicts and doesn't depend on the number of sides, so it can
+/// be used to check content before updating a conflict from it.
+///
+/// The length of the markers is detected from the first start marker. Returns
+/// the first problem which is found.
+pub fn validate_markers(content: &[u8]) -> Result<(), MarkerError> {
+    let marker_len = detect_conflict_marker_len(content).unwrap_or(MIN_CONFLICT_MARKER_LEN);
+    // Line number and offset of the start marker of the current conflict, and
+    // whether it has a section
+    let mut conflict_start: Option<(usize, usize, bool)> = None;
+    let mut offset = 0;
+    for (line_index, line) in content.lines_with_terminator().enumerate() {
+        let error = |kind| MarkerError {
+            kind,
+            line: line_index,
+            offset,
+        };
+        match (parse_conflict_marker(line, marker_len), &mut conflict_start) {
+            (Some(ConflictMarkerLineChar::ConflictStart), None) => {
+                conflict_start = Some((line_index, offset, false));
             }
-        } else {
-            for (content, slice) in zip(contents.iter_mut(), hunk.into_iter()) {
-                content.extend(Vec::from(slice));
+            (Some(ConflictMarkerLineChar::ConflictStart), Some(_)) => {
+                return Err(error(MarkerErrorKind::NestedStart));
             }
+            (Some(ConflictMarkerLineChar::ConflictEnd), None) => {
+                return Err(error(MarkerErrorKind::UnexpectedEnd));
+            }
+            (
+                Some(ConflictMarkerLineChar::ConflictEnd),
+                Some((start_line, start_offset, false)),
+            ) => {
+                return Err(MarkerError {
+                    kind: MarkerErrorKind::NoSections,
+                    line: *start_line,
+                    offset: *start_offset,
+                });
+            }
+            (Some(ConflictMarkerLineChar::ConflictEnd), Some((_, _, true))) => {
+                conflict_start = None;
+            }
+            (
+                Some(
+                    ConflictMarkerLineChar::Add
+                    | ConflictMarkerLineChar::Remove
+                    | ConflictMarkerLineChar::Diff
+                    | ConflictMarkerLineChar::GitAncestor
+                    | ConflictMarkerLineChar::GitSeparator,
+                ),
+                Some((_, _, has_section)),
+            ) => {
+                *has_section = true;
+            }
+            _ => {}
         }
+        offset += line.len();
     }
+    match conflict_start {
+        Some((line, offset, _)) => Err(MarkerError {
+            kind: MarkerErrorKind::Unterminated,
+            line,
+            offset,
+        }),
+        None => Ok(()),
+    }
+}
 
-    // If the user edited the empty placeholder for an absent side, we consider the
-    // conflict resolved.
+/// Rewrites the conflicts in `content` using conflict markers of length
+/// `to_len`.
+///
+/// The current length of the markers, the number of sides, and the conflict
+/// marker style are detected from the first conflict. Returns `None` if
+/// `to_len` is shorter than [`MIN_CONFLICT_MARKER_LEN`], if no conflicts could
+/// be parsed, or if the contents would collide with markers of the requested
+/// length so that the result couldn't be parsed back into the same conflicts.
+pub fn renormalize_markers(content: &[u8], to_len: usize) -> Option<Vec<u8>> {
+    if to_len < MIN_CONFLICT_MARKER_LEN {
+        return None;
+    }
+    let from_len = detect_conflict_marker_len(content)?;
+    let num_sides = infer_num_sides(content, from_len)?;
+    let hunks = parse_conflict(content, num_sides, from_len)?;
+    let options = ConflictMaterializeOptions {
+        marker_len: Some(to_len),
+        // Merge options are unused since the hunks are already merged.
+        ..ConflictMaterializeOptions::new(
+            detect_conflict_marker_style(content, from_len),
+            MergeOptions {
+                hunk_level: FileMergeHunkLevel::Line,
+                same_change: SameChange::Accept,
+                ignore_trailing_newline: false,
+            },
+        )
+    };
+    let mut output = Vec::new();
+    materialize_conflict_hunks(&hunks, &options, to_len, &mut output)
+        .expect("writing to an in-memory buffer should never fail");
+    (parse_conflict(&output, num_sides, to_len).as_ref() == Some(&hunks)).then_some(output)
+}
+
+/// Version of the format written by [`encode_hunks()`].
+const HUNK_ENCODING_VERSION: u8 = 1;
+
+/// Error from decoding hunks with [`decode_hunks()`].
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum HunkDecodeError {
+    #[error("Unsupported hunk encoding version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Encoded hunks are truncated")]
+    Truncated,
+    #[error("Encoded hunk has an even number of terms ({0})")]
+    EvenNumTerms(usize),
+    #[error("Encoded hunks are followed by unexpected data")]
+    TrailingData,
+}
+
+/// Encodes `hunks`, such as returned by [`parse_conflict()`], into a compact
+/// binary format which can be decoded with [`decode_hunks()`], e.g. to cache
+/// the parsed hunks of large files.
+///
+/// The format starts with a version byte, followed by the number of hunks.
+/// Each hunk is encoded as its number of terms followed by the terms, and each
+/// term as its length followed by its content. The numbers are encoded as
+/// little-endian `u32`.
+pub fn encode_hunks(hunks: &[Merge<BString>]) -> Vec<u8> {
+    let encode_len = |len: usize| u32::try_from(len).unwrap().to_le_bytes();
+    let mut data = vec![HUNK_ENCODING_VERSION];
+    data.extend(encode_len(hunks.len()));
+    for hunk in hunks {
+        data.extend(encode_len(hunk.as_slice().len()));
+        for term in hunk.iter() {
+            data.extend(encode_len(term.len()));
+            data.extend_from_slice(term);
+        }
+    }
+    data
+}
+
+/// Decodes hunks encoded with [`encode_hunks()`].
+pub fn decode_hunks(data: &[u8]) -> Result<Vec<Merge<BString>>, HunkDecodeError> {
+    let (&version, mut data) = data.split_first().ok_or(HunkDecodeError::Truncated)?;
+    if version != HUNK_ENCODING_VERSION {
+        return Err(HunkDecodeError::UnsupportedVersion(version));
+    }
+    let num_hunks = take_encoded_len(&mut data)?;
+    let mut hunks = vec![];
+    for _ in 0..num_hunks {
+        let num_terms = take_encoded_len(&mut data)?;
+        if num_terms % 2 == 0 {
+            return Err(HunkDecodeError::EvenNumTerms(num_terms));
+        }
+        let mut terms = vec![];
+        for _ in 0..num_terms {
+            let len = take_encoded_len(&mut data)?;
+            terms.push(BString::from(take_encoded_bytes(&mut data, len)?));
+        }
+        hunks.push(Merge::from_vec(terms));
+    }
+    if !data.is_empty() {
+        return Err(HunkDecodeError::TrailingData);
+    }
+    Ok(hunks)
+}
+
+fn take_encoded_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], HunkDecodeError> {
+    let (taken, rest) = data
+        .split_at_checked(len)
+        .ok_or(HunkDecodeError::Truncated)?;
+    *data = rest;
+    Ok(taken)
+}
+
+fn take_encoded_len(data: &mut &[u8]) -> Result<usize, HunkDecodeError> {
+    let bytes = take_encoded_bytes(data, 4)?.try_into().unwrap();
+    Ok(usize::try_from(u32::from_le_bytes(bytes)).unwrap())
+}
+
+/// Parses conflict markers in `content` and returns an updated version of
+/// `file_ids` with the new contents. If no (valid) conflict markers remain, a
+/// single resolves `FileId` will be returned.
+pub async fn update_from_content(
+    file_ids: &Merge<Option<FileId>>,
+    store: &Store,
+    path: &RepoPath,
+    content: &[u8],
+    conflict_marker_len: usize,
+) -> BackendResult<Merge<Option<FileId>>> {
+    let (file_ids, _counts) =
+        update_from_content_with_counts(file_ids, store, path, content, conflict_marker_len)
+            .await?;
+    Ok(file_ids)
+}
+
+/// If there is a conflict at the end of the file and a term ends with a
+/// newline, checks whether the original term ended with a newline. If it
+/// didn't, then removes the newline since it was added automatically when
+/// materializing.
+fn remove_materialized_eols(hunks: &mut [Merge<BString>], old_contents: &Merge<BString>) {
+    if let Some(last_hunk) = hunks.last_mut().filter(|hunk| !hunk.is_resolved()) {
+        for (original_content, term) in old_contents.iter().zip_eq(last_hunk.iter_mut()) {
+            if term.last() == Some(&b'\n') && has_no_eol(original_content) {
+                term.pop();
+            }
+        }
+    }
+}
+
+/// Number of conflict hunks resolved by [`update_from_content_with_counts()`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ResolveCounts {
+    /// Number of conflict hunks which were resolved.
+    pub resolved: usize,
+    /// Number of conflict hunks which remain.
+    pub remaining: usize,
+}
+
+/// Like [`update_from_content()`], but also returns how many of the conflict
+/// hunks were resolved.
+pub async fn update_from_content_with_counts(
+    file_ids: &Merge<Option<FileId>>,
+    store: &Store,
+    path: &RepoPath,
+    content: &[u8],
+    conflict_marker_len: usize,
+) -> BackendResult<(Merge<Option<FileId>>, ResolveCounts)> {
+    let simplified_file_ids = file_ids.simplify();
+
+    let old_contents = extract_as_single_hunk(&simplified_file_ids, store, path).await?;
+    let old_hunks = files::merge_hunks(&old_contents, store.merge_options());
+    let num_old_conflicts = match &old_hunks {
+        MergeResult::Resolved(_) => 0,
+        MergeResult::Conflict(hunks) => hunks.iter().filter(|hunk| !hunk.is_resolved()).count(),
+    };
+    let counts_with_remaining = |remaining: usize| ResolveCounts {
+        resolved: num_old_conflicts.saturating_sub(remaining),
+        remaining,
+    };
+
+    // Parse conflicts from the new content using the arity of the simplified
+    // conflicts. If the conflict markers have term hashes, the terms are mapped
+    // back to their original positions.
+    let mut new_hunks = parse_conflict_with_term_hashes(
+        content,
+        simplified_file_ids.num_sides(),
+        conflict_marker_len,
+        &ConflictParseOptions::default(),
+    )
+    .map(|hunks| {
+        hunks
+            .into_iter()
+            .map(|(hunk, hashes)| match (&old_hunks, hashes) {
+                (MergeResult::Conflict(old), Some(hashes)) => {
+                    remap_terms_by_hash(&hunk, &hashes, old).unwrap_or(hunk)
+                }
+                _ => hunk,
+            })
+            .collect_vec()
+    });
+
+    if let Some(hunks) = &mut new_hunks {
+        remove_materialized_eols(hunks, &old_contents);
+    }
+
+    // Check if the new hunks are unchanged. This makes sure that unchanged file
+    // conflicts aren't updated to partially-resolved contents.
+    let unchanged = match (&old_hunks, &new_hunks) {
+        (MergeResult::Resolved(old), None) => old == content,
+        (MergeResult::Conflict(old), Some(new)) => old == new,
+        (MergeResult::Resolved(_), Some(_)) | (MergeResult::Conflict(_), None) => false,
+    };
+    if unchanged {
+        return Ok((file_ids.clone(), counts_with_remaining(num_old_conflicts)));
+    }
+
+    // Comment lines written along with the conflict markers are removed, even
+    // if all the conflict markers were removed
+    let resolved_content = match &old_hunks {
+        MergeResult::Resolved(_) => Cow::Borrowed(content),
+        MergeResult::Conflict(_) => strip_comment_lines(content, conflict_marker_len),
+    };
+    let Some(hunks) = new_hunks else {
+        // Either there are no markers or they don't have the expected arity
+        let file_id = store.write_file(path, &mut &resolved_content[..]).await?;
+        return Ok((Merge::normal(file_id), counts_with_remaining(0)));
+    };
+    let num_new_conflicts = hunks.iter().filter(|hunk| !hunk.is_resolved()).count();
+
+    // Now write the new files contents we found by parsing the file with conflict
+    // markers.
+    match reassemble_conflict(store, path, file_ids, &hunks).await? {
+        Some(new_file_ids) => Ok((new_file_ids, counts_with_remaining(num_new_conflicts))),
+        // If the user edited the empty placeholder for an absent side, we consider
+        // the conflict resolved.
+        None => {
+            let file_id = store.write_file(path, &mut &resolved_content[..]).await?;
+            Ok((Merge::normal(file_id), counts_with_remaining(0)))
+        }
+    }
+}
+
+/// Content of a partially resolved file conflict, as returned by
+/// [`partial_resolution()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PartialResolution {
+    /// Content of the resolved regions of the file in order, including the
+    /// regions which were resolved before. Adjacent resolved regions are
+    /// joined.
+    pub resolved: Vec<BString>,
+    /// The conflict with the content of the resolved regions written to all
+    /// terms, so that only the conflicts which still have markers remain. This
+    /// is resolved if no conflicts remain.
+    pub remaining: Merge<Option<FileId>>,
+}
+
+/// Parses conflict markers in `content`, which was materialized from
+/// `file_ids` and then partially resolved, and splits it into the resolved
+/// regions and the conflicts which remain. Unlike [`update_from_content()`],
+/// the resolved regions are returned separately, so that the progress can be
+/// saved and the remaining conflict resolved later.
+pub async fn partial_resolution(
+    store: &Store,
+    path: &RepoPath,
+    file_ids: &Merge<Option<FileId>>,
+    content: &[u8],
+    conflict_marker_len: usize,
+) -> BackendResult<PartialResolution> {
+    let simplified_file_ids = file_ids.simplify();
+    let old_contents = extract_as_single_hunk(&simplified_file_ids, store, path).await?;
+    if let Some(mut hunks) = parse_conflict(
+        content,
+        simplified_file_ids.num_sides(),
+        conflict_marker_len,
+    ) {
+        remove_materialized_eols(&mut hunks, &old_contents);
+        if let Some(remaining) = reassemble_conflict(store, path, file_ids, &hunks).await? {
+            let resolved = hunks
+                .iter()
+                .filter_map(|hunk| hunk.as_resolved().cloned())
+                .collect();
+            return Ok(PartialResolution {
+                resolved,
+                remaining,
+            });
+        }
+    }
+    // Either there are no markers, or the user edited the empty placeholder for
+    // an absent side, in which case we consider the conflict resolved.
+    let file_id = store.write_file(path, &mut &content[..]).await?;
+    Ok(PartialResolution {
+        resolved: vec![content.into()],
+        remaining: Merge::normal(file_id),
+    })
+}
+
+/// Writes the terms of the conflict `hunks`, such as parsed from materialized
+/// content, and returns the updated version of `file_ids`. The conflict hunks
+/// must have as many sides as the simplified `file_ids`. If the conflict was
+/// simplified, the result is expanded to the shape of `file_ids`.
+///
+/// Returns `None` if a term which is absent in `file_ids` would get content,
+/// since the result can't be represented as an update of `file_ids`.
+pub async fn reassemble_conflict(
+    store: &Store,
+    path: &RepoPath,
+    file_ids: &Merge<Option<FileId>>,
+    hunks: &[Merge<BString>],
+) -> BackendResult<Option<Merge<Option<FileId>>>> {
+    let simplified_file_ids = file_ids.simplify();
+    if let Some(hunk) = hunks
+        .iter()
+        .find(|hunk| !hunk.is_resolved() && hunk.num_sides() != simplified_file_ids.num_sides())
+    {
+        return Err(BackendError::Other(
+            format!(
+                "Conflict hunk has {} sides, but the conflict in {} has {} sides",
+                hunk.num_sides(),
+                path.as_internal_file_string(),
+                simplified_file_ids.num_sides()
+            )
+            .into(),
+        ));
+    }
+    let contents = assemble_conflict_terms(&simplified_file_ids, hunks);
     if zip(contents.iter(), simplified_file_ids.iter())
         .any(|(content, file_id)| file_id.is_none() && !content.is_empty())
     {
-        let file_id = store.write_file(path, &mut &content[..]).await?;
-        return Ok(Merge::normal(file_id));
+        return Ok(None);
     }
+    let new_file_ids =
+        write_conflict_terms(store, path, file_ids, &simplified_file_ids, &contents)?;
+    Ok(Some(new_file_ids))
+}
 
-    // Now write the new files contents we found by parsing the file with conflict
-    // markers.
+/// Simplifies the file conflict `file_ids` so that its terms only differ in
+/// the regions which conflict. The regions which can be merged are written to
+/// every term, and terms which then cancel out are removed, so the conflict
+/// becomes smaller while it still materializes to the same content. Returns a
+/// resolved value if the whole file can be merged.
+///
+/// If a term which is absent would get content, the conflict is only
+/// simplified as by [`Merge::simplify()`].
+pub async fn simplify_conflict(
+    store: &Store,
+    path: &RepoPath,
+    file_ids: &Merge<Option<FileId>>,
+) -> BackendResult<Merge<Option<FileId>>> {
+    let simplified_file_ids = file_ids.simplify();
+    let contents = extract_as_single_hunk(&simplified_file_ids, store, path).await?;
+    let hunks = match files::merge_hunks(&contents, store.merge_options()) {
+        MergeResult::Resolved(content) => vec![Merge::resolved(content)],
+        MergeResult::Conflict(hunks) => hunks,
+    };
+    let new_file_ids = reassemble_conflict(store, path, &simplified_file_ids, &hunks).await?;
+    Ok(match new_file_ids {
+        Some(new_file_ids) => new_file_ids.simplify(),
+        None => simplified_file_ids,
+    })
+}
+
+/// Concatenates `hunks` into the contents of each term of the simplified
+/// conflict. Resolved hunks are added to all terms.
+fn assemble_conflict_terms<'a>(
+    simplified_file_ids: &Merge<Option<FileId>>,
+    hunks: impl IntoIterator<Item = &'a Merge<BString>>,
+) -> Merge<Vec<u8>> {
+    let mut contents = simplified_file_ids.map(|_| vec![]);
+    for hunk in hunks {
+        if let Some(slice) = hunk.as_resolved() {
+            for content in contents.iter_mut() {
+                content.extend_from_slice(slice);
+            }
+        } else {
+            for (content, slice) in zip(contents.iter_mut(), hunk.iter()) {
+                content.extend_from_slice(slice);
+            }
+        }
+    }
+    contents
+}
+
+/// Writes the `contents` of each term of the simplified conflict. If the
+/// conflict was simplified, the result is expanded to the shape of the original
+/// `file_ids`.
+fn write_conflict_terms(
+    store: &Store,
+    path: &RepoPath,
+    file_ids: &Merge<Option<FileId>>,
+    simplified_file_ids: &Merge<Option<FileId>>,
+    contents: &Merge<Vec<u8>>,
+) -> BackendResult<Merge<Option<FileId>>> {
     // TODO: Write these concurrently
     let new_file_ids: Vec<Option<FileId>> = zip(contents.iter(), simplified_file_ids.iter())
         .map(|(content, file_id)| -> BackendResult<Option<FileId>> {
@@ -1039,10 +2917,1056 @@ pub async fn update_from_content(
     Ok(new_file_ids)
 }
 
+/// How to resolve a conflict hunk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HunkChoice {
+    /// Leave the hunk conflicted.
+    Keep,
+    /// Take the contents of the side (add) at the given index.
+    Side(usize),
+    /// Take the contents of the base (remove) at the given index.
+    Base(usize),
+}
+
+/// Resolves the conflict hunks of the file conflict `file_ids` according to
+/// `choices`, which apply to the conflict hunks in order. Hunks without a
+/// choice are left conflicted.
+///
+/// Returns the updated file ids, which are resolved if no conflict hunks
+/// remain.
+pub async fn resolve_hunks(
+    store: &Store,
+    path: &RepoPath,
+    file_ids: &Merge<Option<FileId>>,
+    choices: &[HunkChoice],
+) -> BackendResult<Merge<Option<FileId>>> {
+    let simplified_file_ids = file_ids.simplify();
+    let contents = extract_as_single_hunk(&simplified_file_ids, store, path).await?;
+    let hunks = match files::merge_hunks(&contents, store.merge_options()) {
+        MergeResult::Resolved(content) => vec![Merge::resolved(content)],
+        MergeResult::Conflict(hunks) => hunks,
+    };
+    let num_conflicts = hunks.iter().filter(|hunk| !hunk.is_resolved()).count();
+    if choices.len() > num_conflicts {
+        return Err(BackendError::Other(
+            format!(
+                "Got {} hunk choices for {num_conflicts} conflict hunks in {}",
+                choices.len(),
+                path.as_internal_file_string()
+            )
+            .into(),
+        ));
+    }
+    let mut choices = choices.iter();
+    let hunks: Vec<Merge<BString>> = hunks
+        .into_iter()
+        .map(|hunk| -> BackendResult<_> {
+            if hunk.is_resolved() {
+                return Ok(hunk);
+            }
+            let chosen = match choices.next().copied().unwrap_or(HunkChoice::Keep) {
+                HunkChoice::Keep => return Ok(hunk),
+                HunkChoice::Side(index) => hunk.get_add(index),
+                HunkChoice::Base(index) => hunk.get_remove(index),
+            };
+            let chosen = chosen.ok_or_else(|| {
+                BackendError::Other(
+                    format!(
+                        "Hunk choice is out of range for the conflict in {}",
+                        path.as_internal_file_string()
+                    )
+                    .into(),
+                )
+            })?;
+            Ok(Merge::resolved(chosen.clone()))
+        })
+        .try_collect()?;
+
+    if hunks.iter().all(|hunk| hunk.is_resolved()) {
+        let content = hunks
+            .iter()
+            .flat_map(|hunk| hunk.first().iter())
+            .copied()
+            .collect_vec();
+        let file_id = store.write_file(path, &mut content.as_slice()).await?;
+        return Ok(Merge::normal(file_id));
+    }
+    let contents = assemble_conflict_terms(&simplified_file_ids, &hunks);
+    write_conflict_terms(store, path, file_ids, &simplified_file_ids, &contents)
+}
+
+/// Resolves the conflict hunks of many files at once. See [`resolve_hunks()`]
+/// for how the choices for each path are applied.
+pub async fn resolve_tree(
+    store: &Store,
+    choices: &HashMap<RepoPathBuf, (Merge<Option<FileId>>, Vec<HunkChoice>)>,
+) -> BackendResult<HashMap<RepoPathBuf, Merge<Option<FileId>>>> {
+    let mut resolved = HashMap::new();
+    for (path, (file_ids, hunk_choices)) in choices {
+        let new_file_ids = resolve_hunks(store, path, file_ids, hunk_choices).await?;
+        resolved.insert(path.clone(), new_file_ids);
+    }
+    Ok(resolved)
+}
+
+/// Selects a whole term of a simplified file conflict.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SideSelector {
+    /// The side (add) at the given index.
+    Side(usize),
+    /// The base (remove) at the given index.
+    Base(usize),
+}
+
+/// Returns the number of lines added or removed by the `resolved` content
+/// compared to resolving the conflict by taking the `baseline` term.
+///
+/// This measures how much a manual resolution differs from what picking one
+/// side would have produced.
+pub async fn resolution_churn(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    resolved: &[u8],
+    baseline: SideSelector,
+) -> BackendResult<usize> {
+    let conflict = conflict.simplify();
+    let term = match baseline {
+        SideSelector::Side(index) => conflict.get_add(index),
+        SideSelector::Base(index) => conflict.get_remove(index),
+    };
+    let term = term.ok_or_else(|| {
+        BackendError::Other(
+            format!(
+                "Baseline {baseline:?} is out of range for the conflict in {}",
+                path.as_internal_file_string()
+            )
+            .into(),
+        )
+    })?;
+    let baseline_content = get_file_contents(store, path, term).await?;
+    let churn = ContentDiff::by_line([baseline_content.as_slice(), resolved])
+        .hunks()
+        .filter(|hunk| hunk.kind == DiffHunkKind::Different)
+        .map(|hunk| {
+            hunk.contents
+                .iter()
+                .map(|content| content.lines_with_terminator().count())
+                .sum::<usize>()
+        })
+        .sum();
+    Ok(churn)
+}
+
+/// Returns true if the prior resolution of a conflict can be offered as the
+/// resolution of the `conflict`, which is the case if the sides of the
+/// simplified conflict are the same as the `prior_sides` (in any order), and
+/// if the `prior_resolution` doesn't contain conflict markers.
+pub fn matches_prior_resolution(
+    conflict: &Merge<BString>,
+    prior_sides: &[&[u8]],
+    prior_resolution: &[u8],
+) -> bool {
+    let conflict = conflict.simplify();
+    if conflict.is_resolved() {
+        return false;
+    }
+    let sides = conflict
+        .adds()
+        .map(|side| side.as_slice())
+        .sorted()
+        .collect_vec();
+    let prior_sides = prior_sides.iter().copied().sorted().collect_vec();
+    sides == prior_sides
+        && !prior_resolution
+            .lines_with_terminator()
+            .filter_map(parse_conflict_marker_any_len)
+            .any(|marker| {
+                marker.kind == ConflictMarkerLineChar::ConflictStart
+                    && marker.len >= MIN_CONFLICT_MARKER_LEN
+            })
+}
+
+/// Resolves conflict markers in `content` without the original conflict, which
+/// is useful for recovering when the conflict object can't be read.
+///
+/// The number of sides and the length of the markers are inferred from the
+/// first well-formed conflict. Each conflict hunk is merged again, and if all
+/// of them merge cleanly (or there are no conflicts), the resolved content is
+/// written. The file is assumed to be non-executable. Returns `None` if any
+/// conflict hunk remains, or if there is a conflict start marker but no
+/// conflict can be parsed.
+pub async fn resolve_inferred(
+    store: &Store,
+    path: &RepoPath,
+    content: &[u8],
+) -> BackendResult<Option<TreeValue>> {
+    let resolved = match detect_conflict_marker_len(content) {
+        Some(marker_len) => {
+            let Some(hunks) = infer_num_sides(content, marker_len)
+                .and_then(|num_sides| parse_conflict(content, num_sides, marker_len))
+            else {
+                return Ok(None);
+            };
+            let mut resolved = BString::default();
+            for hunk in &hunks {
+                let Some(content) = files::try_merge(hunk, store.merge_options()) else {
+                    return Ok(None);
+                };
+                resolved.extend_from_slice(&content);
+            }
+            resolved
+        }
+        None => BString::from(content),
+    };
+    let id = store.write_file(path, &mut resolved.as_slice()).await?;
+    Ok(Some(TreeValue::File {
+        id,
+        executable: false,
+        copy_id: CopyId::placeholder(),
+    }))
+}
+
+/// Command to resolve a 2-sided file conflict, similar to a Git merge driver.
+///
+/// In `args`, the placeholders `%O`, `%A`, and `%B` are replaced by the paths
+/// of files containing the base, the first side, and the second side, and `%P`
+/// is replaced by the path of the conflicted file in the repo. Placeholders
+/// are also replaced within arguments, such as in `--base=%O`. The command
+/// should write the merged content to the `%A` file and exit successfully if
+/// it resolved the conflict.
+#[derive(Clone, Debug)]
+pub struct MergeCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// Writes each term of `contents` to a file in `dir`. The sides are named
+/// `side-1`, `side-2`, etc. and the bases are named `base-1`, `base-2`, etc.
+///
+/// Returns the paths of the files in the same order as the terms.
+pub fn write_sides_to_dir(contents: &Merge<BString>, dir: &Path) -> io::Result<Merge<PathBuf>> {
+    let paths: Vec<PathBuf> = contents
+        .iter()
+        .enumerate()
+        .map(|(index, content)| {
+            let name = if index % 2 == 0 {
+                format!("side-{}", index / 2 + 1)
+            } else {
+                format!("base-{}", index / 2 + 1)
+            };
+            let file_path = dir.join(name);
+            fs::write(&file_path, content)?;
+            Ok::<_, io::Error>(file_path)
+        })
+        .try_collect()?;
+    Ok(Merge::from_vec(paths))
+}
+
+/// Reads the merged content from `merged_file` and writes it to the store as
+/// the resolution of the file at `path`.
+pub async fn resolve_from_merged_file(
+    store: &Store,
+    path: &RepoPath,
+    merged_file: &Path,
+    executable: bool,
+    copy_id: CopyId,
+) -> BackendResult<TreeValue> {
+    let content = fs::read(merged_file).map_err(|err| {
+        BackendError::Other(
+            format!(
+                "Failed to read merged file {} for {}: {err}",
+                merged_file.display(),
+                path.as_internal_file_string()
+            )
+            .into(),
+        )
+    })?;
+    let id = store.write_file(path, &mut content.as_slice()).await?;
+    Ok(TreeValue::File {
+        id,
+        executable,
+        copy_id,
+    })
+}
+
+/// Resolves `conflict` to the `content` chosen by the user, keeping the type of
+/// the conflicting values. Absent values are ignored.
+///
+/// If all values are symlinks, `content` is the target of the resolved
+/// symlink. If all values are files, `content` is the content of the resolved
+/// file, and its executable bit and copy id are resolved from the values. The
+/// conflict is returned unchanged if the types of the values disagree, if they
+/// are of a type which can't be represented as content, or if the executable
+/// bit or the copy id can't be resolved.
+pub async fn resolve_to_tree_value(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+    content: &[u8],
+) -> BackendResult<MergedTreeValue> {
+    let values = conflict.iter().flatten().collect_vec();
+    if !values.is_empty()
+        && values
+            .iter()
+            .all(|value| matches!(value, TreeValue::Symlink(_)))
+    {
+        let target = str::from_utf8(content).map_err(|err| {
+            BackendError::Other(
+                format!(
+                    "Symlink target for {} is not valid UTF-8: {err}",
+                    path.as_internal_file_string()
+                )
+                .into(),
+            )
+        })?;
+        let id = store.write_symlink(path, target).await?;
+        return Ok(Merge::normal(TreeValue::Symlink(id)));
+    }
+    if let (Some(executable_bits), Some(copy_ids)) =
+        (conflict.to_executable_merge(), conflict.to_copy_id_merge())
+        && let (Some(executable), Some(copy_id)) = (
+            resolve_file_executable(&executable_bits),
+            resolve_file_copy_id(&copy_ids),
+        )
+    {
+        let id = store.write_file(path, &mut &content[..]).await?;
+        return Ok(Merge::normal(TreeValue::File {
+            id,
+            executable,
+            copy_id,
+        }));
+    }
+    Ok(conflict.clone())
+}
+
+/// Operation on a line of a side of a conflict, as applied by
+/// [`apply_edit_script()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineOp {
+    /// Keep the line as is.
+    Keep,
+    /// Remove the line.
+    Delete,
+    /// Replace the line, including its terminating newline, with the content.
+    /// The content may span any number of lines.
+    Replace(BString),
+}
+
+/// Resolves `conflict` by applying `ops` to the lines of side `side`
+/// (0-based) of the conflict, where the nth op applies to the nth line. Lines
+/// past the end of `ops` are kept. The resolved file keeps the executable bit
+/// and copy id of the side.
+///
+/// Returns an error if the side isn't a file or if there are more ops than
+/// lines.
+pub async fn apply_edit_script(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+    side: usize,
+    ops: &[LineOp],
+) -> BackendResult<TreeValue> {
+    let Some(Some(TreeValue::File {
+        id,
+        executable,
+        copy_id,
+    })) = conflict.get_add(side)
+    else {
+        return Err(BackendError::Other(
+            format!(
+                "Side #{} of the conflict at {} is not a file",
+                side + 1,
+                path.as_internal_file_string()
+            )
+            .into(),
+        ));
+    };
+    let content = get_file_contents(store, path, &Some(id.clone())).await?;
+    let lines = content.lines_with_terminator().collect_vec();
+    if ops.len() > lines.len() {
+        return Err(BackendError::Other(
+            format!(
+                "Edit script for {} has {} ops, but side #{} has {} lines",
+                path.as_internal_file_string(),
+                ops.len(),
+                side + 1,
+                lines.len()
+            )
+            .into(),
+        ));
+    }
+    let mut resolved = BString::default();
+    for (index, line) in lines.iter().enumerate() {
+        match ops.get(index).unwrap_or(&LineOp::Keep) {
+            LineOp::Keep => resolved.extend_from_slice(line),
+            LineOp::Delete => {}
+            LineOp::Replace(replacement) => resolved.extend_from_slice(replacement),
+        }
+    }
+    let id = store.write_file(path, &mut resolved.as_slice()).await?;
+    Ok(TreeValue::File {
+        id,
+        executable: *executable,
+        copy_id: copy_id.clone(),
+    })
+}
+
+/// Resolves a 2-sided file conflict by running `cmd` on the sides.
+///
+/// The command is run without stdin and with its stdout discarded.
+///
+/// Returns `None` if the command exits with a non-zero status, or if the
+/// executable bit or the copy id of the file can't be resolved, in which case
+/// the conflict should be left as is.
+pub async fn resolve_with_command(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+    cmd: &MergeCommand,
+) -> BackendResult<Option<TreeValue>> {
+    let to_backend_error = |err: io::Error| {
+        BackendError::Other(
+            format!(
+                "Failed to run merge command {} for {}: {err}",
+                cmd.program,
+                path.as_internal_file_string()
+            )
+            .into(),
+        )
+    };
+    let Some(file) = try_materialize_file_conflict_value(store, path, conflict).await? else {
+        return Err(BackendError::Other(
+            format!(
+                "Conflict at {} is not a file conflict",
+                path.as_internal_file_string()
+            )
+            .into(),
+        ));
+    };
+    if file.contents.num_sides() != 2 {
+        return Err(BackendError::Other(
+            format!(
+                "Merge command can only resolve 2-sided conflicts, but {} has {} sides",
+                path.as_internal_file_string(),
+                file.contents.num_sides()
+            )
+            .into(),
+        ));
+    }
+    let (Some(executable), Some(copy_id)) = (file.executable, file.copy_id) else {
+        return Ok(None);
+    };
+
+    let temp_dir = tempfile::Builder::new()
+        .prefix("jj-merge-")
+        .tempdir()
+        .map_err(to_backend_error)?;
+    let paths = write_sides_to_dir(&file.contents, temp_dir.path()).map_err(to_backend_error)?;
+    let [side1, base, side2] = paths.as_slice() else {
+        unreachable!("conflict should have 2 sides");
+    };
+    let repo_path = OsString::from(path.as_internal_file_string());
+    let placeholders = [
+        ("%O", base.as_os_str()),
+        ("%A", side1.as_os_str()),
+        ("%B", side2.as_os_str()),
+        ("%P", repo_path.as_os_str()),
+    ];
+    let args = cmd
+        .args
+        .iter()
+        .map(|arg| substitute_placeholders(arg, &placeholders));
+    let status = Command::new(&cmd.program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()
+        .map_err(to_backend_error)?;
+    if !status.success() {
+        return Ok(None);
+    }
+    let value = resolve_from_merged_file(store, path, side1, executable, copy_id).await?;
+    Ok(Some(value))
+}
+
+/// Replaces each occurrence of the placeholders in `arg` by their values.
+fn substitute_placeholders(arg: &str, placeholders: &[(&str, &OsStr)]) -> OsString {
+    let mut substituted = OsString::new();
+    let mut rest = arg;
+    while let Some(c) = rest.chars().next() {
+        if let Some((placeholder, value)) = placeholders
+            .iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            substituted.push(value);
+            rest = &rest[placeholder.len()..];
+        } else {
+            substituted.push(&rest[..c.len_utf8()]);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    substituted
+}
+
+/// Merges file contents with knowledge of their format, such as JSON or TOML,
+/// where merging line by line gives poor results.
+pub trait SemanticMerger {
+    /// Merges the `sides` of a conflict, which all have `base` as base.
+    /// Returns the merged content, or `None` if the sides couldn't be merged.
+    fn merge(&self, base: &[u8], sides: &[&[u8]]) -> Option<Vec<u8>>;
+}
+
+/// Resolves a file conflict by merging its sides with `merger`.
+///
+/// Returns `None` if the merger couldn't merge the sides, if the bases of the
+/// conflict differ, or if the executable bit or the copy id of the file can't
+/// be resolved, in which case the conflict should be left as is.
+pub async fn semantic_resolve(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+    merger: &dyn SemanticMerger,
+) -> BackendResult<Option<TreeValue>> {
+    let Some(file) = try_materialize_file_conflict_value(store, path, conflict).await? else {
+        return Err(BackendError::Other(
+            format!(
+                "Conflict at {} is not a file conflict",
+                path.as_internal_file_string()
+            )
+            .into(),
+        ));
+    };
+    let (Some(executable), Some(copy_id)) = (file.executable, file.copy_id) else {
+        return Ok(None);
+    };
+    let contents = file.contents.simplify();
+    if !contents.removes().all_equal() {
+        return Ok(None);
+    }
+    let empty = BString::default();
+    let base = contents.get_remove(0).unwrap_or(&empty);
+    let sides = contents.adds().map(|side| side.as_slice()).collect_vec();
+    let Some(merged) = merger.merge(base, &sides) else {
+        return Ok(None);
+    };
+    let id = store.write_file(path, &mut merged.as_slice()).await?;
+    Ok(Some(TreeValue::File {
+        id,
+        executable,
+        copy_id,
+    }))
+}
+
+/// Returns, for each side (add) of the conflict in order, the line ranges
+/// which differ from the base in ascending order.
+///
+/// Every side is compared against the first base (remove) of the simplified
+/// conflict, which is the merge base in the common 3-way case. The ranges are
+/// 0-based half-open line numbers within the side's content. A range may be
+/// empty if lines were only deleted at that position.
+pub async fn conflicting_ranges_per_side(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+) -> BackendResult<Vec<Vec<Range<usize>>>> {
+    let contents = extract_as_single_hunk(&conflict.simplify(), store, path).await?;
+    let empty = BString::default();
+    let base = contents.get_remove(0).unwrap_or(&empty);
+    let ranges = contents
+        .adds()
+        .map(|side| {
+            let diff = ContentDiff::by_line([base, side]);
+            let mut ranges = vec![];
+            let mut line = 0;
+            for hunk in diff.hunks() {
+                let num_lines = hunk.contents[1].lines_with_terminator().count();
+                if hunk.kind == DiffHunkKind::Different {
+                    ranges.push(line..line + num_lines);
+                }
+                line += num_lines;
+            }
+            ranges
+        })
+        .collect();
+    Ok(ranges)
+}
+
+/// Problem found by [`audit_conflicts()`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuditFinding {
+    pub path: RepoPathBuf,
+    pub kind: AuditFindingKind,
+}
+
+/// Reason why a stored conflict doesn't need to be a conflict.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuditFindingKind {
+    /// The value isn't conflicted at all.
+    NotConflicted,
+    /// All sides of the conflict are identical.
+    IdenticalSides,
+    /// The conflict can be resolved trivially, e.g. because some terms cancel
+    /// out.
+    TriviallyResolvable,
+    /// The contents of the file conflict can be merged without conflicts.
+    CleanMerge,
+}
+
+/// Checks whether the given stored conflicts actually need to be conflicts.
+/// Returns a finding for each conflict which could be resolved automatically,
+/// in the order of `conflicts`.
+pub async fn audit_conflicts(
+    store: &Store,
+    conflicts: &[(RepoPathBuf, MergedTreeValue)],
+) -> BackendResult<Vec<AuditFinding>> {
+    let mut findings = vec![];
+    for (path, conflict) in conflicts {
+        let kind = if conflict.is_resolved() {
+            Some(AuditFindingKind::NotConflicted)
+        } else if conflict.adds().all_equal() {
+            Some(AuditFindingKind::IdenticalSides)
+        } else if conflict
+            .resolve_trivial(store.merge_options().same_change)
+            .is_some()
+        {
+            Some(AuditFindingKind::TriviallyResolvable)
+        } else if let Some(file_ids) = conflict.to_file_merge() {
+            let contents = extract_as_single_hunk(&file_ids.simplify(), store, path).await?;
+            files::try_merge(&contents, store.merge_options()).map(|_| AuditFindingKind::CleanMerge)
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            findings.push(AuditFinding {
+                path: path.clone(),
+                kind,
+            });
+        }
+    }
+    Ok(findings)
+}
+
+/// Suspicious part of a resolution found by [`lint_resolution()`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LintWarning {
+    pub kind: LintWarningKind,
+    /// 0-based line number of the problem within the resolution, or `None` if
+    /// the problem concerns the resolution as a whole.
+    pub line: Option<usize>,
+}
+
+/// Kind of a [`LintWarning`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LintWarningKind {
+    /// A conflict marker which doesn't appear in any side of the conflict was
+    /// left in the resolution.
+    LeftoverMarker,
+    /// The resolution still contains the conflict as materialized, so nothing
+    /// was resolved.
+    Unresolved,
+    /// A NUL byte which doesn't appear in any side of the conflict was
+    /// introduced.
+    NulByte,
+    /// The resolution is much larger than the sides of the conflict combined.
+    SizeGrowth,
+}
+
+/// A resolution which is more than this many times larger than the sides of
+/// the conflict combined is reported as [`LintWarningKind::SizeGrowth`].
+const LINT_MAX_SIZE_GROWTH: usize = 2;
+
+/// Checks the `resolved` content of the file conflict `conflict` for common
+/// mistakes, such as leftover conflict markers. The warnings about lines come
+/// first, in the order of the lines, followed by the warnings about the
+/// resolution as a whole.
+pub async fn lint_resolution(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    resolved: &[u8],
+) -> BackendResult<Vec<LintWarning>> {
+    let simplified = conflict.simplify();
+    let contents = extract_as_single_hunk(&simplified, store, path).await?;
+    let mut warnings = vec![];
+    let in_any_side = |line: &[u8]| contents.iter().any(|term| term.contains_str(line));
+    for (index, line) in resolved.lines_with_terminator().enumerate() {
+        if parse_conflict_marker(line, MIN_CONFLICT_MARKER_LEN).is_some() && !in_any_side(line) {
+            warnings.push(LintWarning {
+                kind: LintWarningKind::LeftoverMarker,
+                line: Some(index),
+            });
+        }
+        if line.contains(&b'\0') && !contents.iter().any(|term| term.contains(&b'\0')) {
+            warnings.push(LintWarning {
+                kind: LintWarningKind::NulByte,
+                line: Some(index),
+            });
+        }
+    }
+    // The content is parsed like in `update_from_content()`, so a conflict is
+    // reported as unresolved if updating it from the content would change nothing
+    let marker_len = choose_materialized_conflict_marker_len(&contents);
+    if let MergeResult::Conflict(hunks) = files::merge_hunks(&contents, store.merge_options())
+        && parse_conflict(resolved, simplified.num_sides(), marker_len).is_some_and(|mut parsed| {
+            remove_materialized_eols(&mut parsed, &contents);
+            parsed == hunks
+        })
+    {
+        warnings.push(LintWarning {
+            kind: LintWarningKind::Unresolved,
+            line: None,
+        });
+    }
+    let sides_size: usize = contents.adds().map(|side| side.len()).sum();
+    if resolved.len() > sides_size.saturating_mul(LINT_MAX_SIZE_GROWTH) {
+        warnings.push(LintWarning {
+            kind: LintWarningKind::SizeGrowth,
+            line: None,
+        });
+    }
+    Ok(warnings)
+}
+
+/// Returns the groups of indices of the sides (adds) of the file conflict
+/// `conflict` which have identical contents. Only groups of at least two sides
+/// are returned, in the order of their first side. Absent sides are considered
+/// empty.
+pub async fn duplicate_sides(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+) -> BackendResult<Vec<Vec<usize>>> {
+    let mut groups: Vec<(BString, Vec<usize>)> = vec![];
+    for (index, term) in conflict.adds().enumerate() {
+        let content = get_file_contents(store, path, term).await?;
+        match groups
+            .iter_mut()
+            .find(|(group_content, _)| *group_content == content)
+        {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((content, vec![index])),
+        }
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(_, indices)| indices)
+        .filter(|indices| indices.len() > 1)
+        .collect())
+}
+
+/// Returns true if the terms of the file conflict `conflict` differ only in
+/// the order of their lines, i.e. every term has the same lines the same
+/// number of times. Line terminators are ignored, so that moving the last line
+/// of a file without a terminating newline is still a reordering. Returns
+/// false if all terms are identical.
+pub async fn is_reorder_only(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+) -> BackendResult<bool> {
+    let contents = extract_as_single_hunk(conflict, store, path).await?;
+    if contents.iter().all_equal() {
+        return Ok(false);
+    }
+    Ok(contents
+        .iter()
+        .map(|content| content.lines().sorted_unstable().collect_vec())
+        .all_equal())
+}
+
+/// Statistics about a file conflict.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConflictStats {
+    /// Number of hunks which couldn't be merged.
+    pub num_conflict_hunks: usize,
+    /// Number of sides of the simplified conflict.
+    pub num_sides: usize,
+}
+
+/// Computes statistics about the file conflict `file_ids` by merging the file
+/// contents.
+pub async fn conflict_stats(
+    store: &Store,
+    path: &RepoPath,
+    file_ids: &Merge<Option<FileId>>,
+) -> BackendResult<ConflictStats> {
+    let file_ids = file_ids.simplify();
+    let contents = extract_as_single_hunk(&file_ids, store, path).await?;
+    let num_conflict_hunks = match files::merge_hunks(&contents, store.merge_options()) {
+        MergeResult::Resolved(_) => 0,
+        MergeResult::Conflict(hunks) => hunks.iter().filter(|hunk| !hunk.is_resolved()).count(),
+    };
+    Ok(ConflictStats {
+        num_conflict_hunks,
+        num_sides: file_ids.num_sides(),
+    })
+}
+
+/// Returns a one-line summary of the conflict at `path`, such as
+/// `file.rs: 2 conflicting hunks across 3 sides`.
+///
+/// If the conflict involves other types than files, the types of the sides are
+/// summarized instead.
+pub async fn conflict_summary_line(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &MergedTreeValue,
+) -> BackendResult<String> {
+    let path_str = path.as_internal_file_string();
+    if let Some(file_ids) = conflict.to_file_merge() {
+        let stats = conflict_stats(store, path, &file_ids).await?;
+        return Ok(format!(
+            "{path_str}: {} across {}",
+            pluralize(stats.num_conflict_hunks, "conflicting hunk"),
+            pluralize(stats.num_sides, "side"),
+        ));
+    }
+    let conflict = conflict.simplify();
+    let types = conflict
+        .adds()
+        .map(|term| match term {
+            None => "absent",
+            Some(TreeValue::File { .. }) => "file",
+            Some(TreeValue::Symlink(_)) => "symlink",
+            Some(TreeValue::Tree(_)) => "tree",
+            Some(TreeValue::GitSubmodule(_)) => "Git submodule",
+        })
+        .unique()
+        .join(", ");
+    Ok(format!(
+        "{path_str}: conflict between {types} across {}",
+        pluralize(conflict.num_sides(), "side"),
+    ))
+}
+
+fn pluralize(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
+
     use super::*;
 
+    #[test]
+    fn test_resolve_to_base() {
+        let file_value = |id: u8| TreeValue::File {
+            id: FileId::new(vec![id]),
+            executable: false,
+            copy_id: CopyId::placeholder(),
+        };
+        let path = RepoPath::from_internal_string("file").unwrap();
+        let resolve = |conflict: &MergedTreeValue| resolve_to_base(path, conflict);
+
+        // 1-remove/2-add conflict resolves to the base
+        let conflict = Merge::from_vec(vec![
+            Some(file_value(1)),
+            Some(file_value(0)),
+            Some(file_value(2)),
+        ]);
+        assert_eq!(resolve(&conflict).unwrap(), Some(file_value(0)));
+
+        // The base may be absent (add/add conflict)
+        let conflict = Merge::from_vec(vec![Some(file_value(1)), None, Some(file_value(2))]);
+        assert_eq!(resolve(&conflict).unwrap(), None);
+
+        // Redundant terms are simplified away
+        let conflict = Merge::from_vec(vec![
+            Some(file_value(1)),
+            Some(file_value(0)),
+            Some(file_value(2)),
+            Some(file_value(3)),
+            Some(file_value(3)),
+        ]);
+        assert_eq!(resolve(&conflict).unwrap(), Some(file_value(0)));
+
+        // There's no single base to revert to
+        let conflict = Merge::from_vec(vec![
+            Some(file_value(1)),
+            Some(file_value(0)),
+            Some(file_value(2)),
+            Some(file_value(3)),
+            Some(file_value(4)),
+        ]);
+        assert_matches!(resolve(&conflict), Err(BackendError::Other(_)));
+        assert_matches!(
+            resolve(&Merge::normal(file_value(1))),
+            Err(BackendError::Other(_))
+        );
+    }
+
+    #[test]
+    fn test_file_parts() {
+        let file_value = |id: u8| TreeValue::File {
+            id: FileId::new(vec![id]),
+            executable: id % 2 == 1,
+            copy_id: CopyId::placeholder(),
+        };
+        let symlink_value = TreeValue::Symlink(SymlinkId::new(vec![2]));
+        let tree_value = TreeValue::Tree(TreeId::new(vec![3]));
+
+        let conflict = Merge::from_vec(vec![
+            Some(file_value(1)),
+            Some(file_value(0)),
+            Some(symlink_value.clone()),
+            None,
+            Some(tree_value.clone()),
+        ]);
+        assert_eq!(
+            file_parts(&conflict),
+            (vec![&file_value(0)], vec![&file_value(1)])
+        );
+        assert_eq!(
+            non_file_parts(&conflict),
+            (vec![], vec![&symlink_value, &tree_value])
+        );
+    }
+
+    #[test]
+    fn test_referenced_ids() {
+        let file_value = |id: u8| TreeValue::File {
+            id: FileId::new(vec![id]),
+            executable: false,
+            copy_id: CopyId::placeholder(),
+        };
+
+        let conflict = Merge::from_vec(vec![
+            Some(file_value(1)),
+            Some(file_value(0)),
+            Some(TreeValue::Symlink(SymlinkId::new(vec![2]))),
+            None,
+            Some(TreeValue::Tree(TreeId::new(vec![3]))),
+            Some(file_value(0)),
+            Some(TreeValue::GitSubmodule(CommitId::new(vec![4]))),
+        ]);
+        assert_eq!(
+            referenced_ids(&conflict),
+            vec![
+                (IdKind::File, vec![0]),
+                (IdKind::File, vec![1]),
+                (IdKind::Symlink, vec![2]),
+                (IdKind::Tree, vec![3]),
+                (IdKind::GitSubmodule, vec![4]),
+            ]
+        );
+        assert_eq!(
+            referenced_ids(&Merge::normal(file_value(1))),
+            vec![(IdKind::File, vec![1])]
+        );
+        assert_eq!(referenced_ids(&Merge::absent()), vec![]);
+    }
+
+    #[test]
+    fn test_parts_order() {
+        let file_value = |id: u8| TreeValue::File {
+            id: FileId::new(vec![id]),
+            executable: false,
+            copy_id: CopyId::placeholder(),
+        };
+        let symlink_value = |id: u8| TreeValue::Symlink(SymlinkId::new(vec![id]));
+
+        // Terms in the order: add, remove, add, remove, add, remove, add
+        let conflict = Merge::from_vec(vec![
+            Some(file_value(5)),
+            Some(file_value(7)),
+            Some(symlink_value(3)),
+            Some(file_value(1)),
+            Some(file_value(9)),
+            Some(symlink_value(6)),
+            Some(file_value(5)),
+        ]);
+        assert_eq!(
+            file_parts(&conflict),
+            (
+                vec![&file_value(7), &file_value(1)],
+                vec![&file_value(5), &file_value(9), &file_value(5)]
+            )
+        );
+        assert_eq!(
+            non_file_parts(&conflict),
+            (vec![&symlink_value(6)], vec![&symlink_value(3)])
+        );
+        assert_eq!(
+            referenced_ids(&conflict),
+            vec![
+                (IdKind::File, vec![7]),
+                (IdKind::File, vec![1]),
+                (IdKind::Symlink, vec![6]),
+                (IdKind::File, vec![5]),
+                (IdKind::Symlink, vec![3]),
+                (IdKind::File, vec![9]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_matches_prior_resolution() {
+        let conflict = Merge::from_vec(vec![
+            BString::from("left\n"),
+            BString::from("base\n"),
+            BString::from("right\n"),
+        ]);
+        let resolution = b"merged\n";
+
+        // The sides match, possibly in a different order
+        assert!(matches_prior_resolution(
+            &conflict,
+            &[b"left\n", b"right\n"],
+            resolution
+        ));
+        assert!(matches_prior_resolution(
+            &conflict,
+            &[b"right\n", b"left\n"],
+            resolution
+        ));
+
+        // The sides don't match
+        assert!(!matches_prior_resolution(
+            &conflict,
+            &[b"left\n", b"other\n"],
+            resolution
+        ));
+        assert!(!matches_prior_resolution(
+            &conflict,
+            &[b"left\n"],
+            resolution
+        ));
+
+        // The prior resolution is still conflicted
+        assert!(!matches_prior_resolution(
+            &conflict,
+            &[b"left\n", b"right\n"],
+            b"<<<<<<< Conflict 1 of 1\n"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_file_copy_id() {
+        fn resolve<const N: usize>(values: [Option<u8>; N]) -> Option<CopyId> {
+            let merge =
+                Merge::from_vec(values.to_vec()).map(|id| id.map(|id| CopyId::new(vec![id])));
+            resolve_file_copy_id(&merge)
+        }
+        let copy_id = |id| Some(CopyId::new(vec![id]));
+
+        // The sides agree
+        assert_eq!(resolve([Some(1)]), copy_id(1));
+        assert_eq!(resolve([Some(1), Some(1), Some(1)]), copy_id(1));
+        assert_eq!(resolve([Some(2), Some(1), Some(1)]), copy_id(2));
+
+        // The sides disagree
+        assert_eq!(resolve([Some(2), Some(1), Some(3)]), None);
+
+        // Deleted on one side, so pick the original copy id
+        assert_eq!(resolve([Some(1), Some(1), None]), copy_id(1));
+    }
+
     #[test]
     fn test_resolve_file_executable() {
         fn resolve<const N: usize>(values: [Option<bool>; N]) -> Option<bool> {
@@ -1089,4 +4013,23 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn test_substitute_placeholders() {
+        let placeholders = [
+            ("%O", OsStr::new("/tmp/base-1")),
+            ("%A", OsStr::new("/tmp/side-1")),
+            ("%P", OsStr::new("dir/file")),
+        ];
+        let substitute = |arg: &str| substitute_placeholders(arg, &placeholders);
+
+        assert_eq!(substitute("%A"), "/tmp/side-1");
+        // Placeholders are replaced within arguments
+        assert_eq!(substitute("--base=%O"), "--base=/tmp/base-1");
+        assert_eq!(substitute("%A:%P"), "/tmp/side-1:dir/file");
+        // Unknown placeholders and other characters are kept
+        assert_eq!(substitute("%B 100%"), "%B 100%");
+        assert_eq!(substitute("\u{e9}%A"), "\u{e9}/tmp/side-1");
+        assert_eq!(substitute(""), "");
+    }
 }
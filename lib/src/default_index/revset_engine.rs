@@ -1853,6 +1853,7 @@ mod tests {
             let options = MergeOptions {
                 hunk_level: FileMergeHunkLevel::Line,
                 same_change: SameChange::Accept,
+                ignore_trailing_newline: false,
             };
             diff_match_lines(&left1, &left2, &matcher, &options).unwrap()
         };
@@ -1878,6 +1879,7 @@ mod tests {
             let options = MergeOptions {
                 hunk_level: FileMergeHunkLevel::Line,
                 same_change: SameChange::Accept,
+                ignore_trailing_newline: false,
             };
             diff_match_lines(&conflict1, &conflict2, &matcher, &options).unwrap()
         };
@@ -1907,6 +1909,7 @@ mod tests {
             let options = MergeOptions {
                 hunk_level: FileMergeHunkLevel::Line,
                 same_change: SameChange::Accept,
+                ignore_trailing_newline: false,
             };
             diff_match_lines(&base, &conflict2, &matcher, &options).unwrap()
         };
@@ -306,11 +306,54 @@ pub fn try_merge<T: AsRef<[u8]>>(inputs: &Merge<T>, options: &MergeOptions) -> O
     merge_inner(inputs, options)
 }
 
+/// Merges `inputs` at each of the hunk `levels` in order, and returns the first
+/// fully-resolved content. This can resolve conflicts which only merge cleanly
+/// at a different granularity. If none of the levels resolves the conflict,
+/// returns the hunks of the finest level. The `hunk_level` of `options` is
+/// only used if `levels` is empty.
+pub fn merge_with_escalation<T: AsRef<[u8]>>(
+    inputs: &Merge<T>,
+    options: &MergeOptions,
+    levels: &[FileMergeHunkLevel],
+) -> MergeResult {
+    // Higher is finer
+    let fineness = |level: FileMergeHunkLevel| match level {
+        FileMergeHunkLevel::Line => 0,
+        FileMergeHunkLevel::Word => 1,
+    };
+    let mut finest_conflict: Option<(FileMergeHunkLevel, MergeResult)> = None;
+    for &level in levels {
+        let level_options = MergeOptions {
+            hunk_level: level,
+            ..options.clone()
+        };
+        let result = merge_hunks(inputs, &level_options);
+        if let MergeResult::Resolved(_) = result {
+            return result;
+        }
+        if finest_conflict
+            .as_ref()
+            .is_none_or(|(finest_level, _)| fineness(level) > fineness(*finest_level))
+        {
+            finest_conflict = Some((level, result));
+        }
+    }
+    match finest_conflict {
+        Some((_, result)) => result,
+        None => merge_hunks(inputs, options),
+    }
+}
+
 fn merge_inner<'input, T, B>(inputs: &'input Merge<T>, options: &MergeOptions) -> B
 where
     T: AsRef<[u8]>,
     B: FromMergeHunks<'input>,
 {
+    if options.ignore_trailing_newline
+        && let Some(content) = try_merge_ignoring_trailing_newline(inputs, options)
+    {
+        return B::from_hunks([MergeHunk::Owned(Merge::resolved(content))]);
+    }
     // TODO: Using the first remove as base (first in the inputs) is how it's
     // usually done for 3-way conflicts. Are there better heuristics when there are
     // more than 3 parts?
@@ -325,6 +368,39 @@ where
     }
 }
 
+/// Merges `inputs` without their terminating newlines, then merges the
+/// terminating newline separately. If the sides disagree about the terminating
+/// newline, it is kept.
+///
+/// Returns `None` if the inputs agree about the terminating newline (so there's
+/// nothing to ignore), or if the content can't be merged cleanly.
+fn try_merge_ignoring_trailing_newline<T: AsRef<[u8]>>(
+    inputs: &Merge<T>,
+    options: &MergeOptions,
+) -> Option<BString> {
+    let has_eol = inputs.map(|input| input.as_ref().ends_with(b"\n"));
+    if has_eol.iter().all_equal() {
+        return None;
+    }
+    let stripped = inputs.map(|input| {
+        let input = input.as_ref();
+        input.strip_suffix(b"\n").unwrap_or(input)
+    });
+    let options = MergeOptions {
+        ignore_trailing_newline: false,
+        ..options.clone()
+    };
+    let mut content = try_merge(&stripped, &options)?;
+    if has_eol
+        .resolve_trivial(options.same_change)
+        .copied()
+        .unwrap_or(true)
+    {
+        content.push(b'\n');
+    }
+    Some(content)
+}
+
 fn merge_hunk_by_word(inputs: Merge<&BStr>, same_change: SameChange) -> MergeHunk<'_> {
     if inputs.is_resolved() {
         return MergeHunk::Borrowed(inputs);
@@ -489,6 +565,7 @@ fn resolve_diff_hunks<'input>(
 
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
     use indoc::indoc;
 
     use super::*;
@@ -855,6 +932,7 @@ mod tests {
         let options = MergeOptions {
             hunk_level: FileMergeHunkLevel::Line,
             same_change: SameChange::Accept,
+            ignore_trailing_newline: false,
         };
         let merge_hunks = |inputs: &_| merge_hunks(inputs, &options);
         // Unchanged and empty on all sides
@@ -971,6 +1049,7 @@ mod tests {
         let options = MergeOptions {
             hunk_level: FileMergeHunkLevel::Line,
             same_change: SameChange::Accept,
+            ignore_trailing_newline: false,
         };
         let merge_hunks = |inputs: &_| merge_hunks(inputs, &options);
         let merge = |inputs: &_| merge(inputs, &options);
@@ -1078,11 +1157,56 @@ mod tests {
         assert_eq!(merge(&conflict([left, base, right])), resolved(merged));
     }
 
+    #[test]
+    fn test_merge_ignoring_trailing_newline() {
+        let plain_options = MergeOptions {
+            hunk_level: FileMergeHunkLevel::Line,
+            same_change: SameChange::Accept,
+            ignore_trailing_newline: false,
+        };
+        let options = MergeOptions {
+            ignore_trailing_newline: true,
+            ..plain_options.clone()
+        };
+        let plain_merge = |inputs: &_| merge(inputs, &plain_options);
+        let merge = |inputs: &_| merge(inputs, &options);
+
+        // Both sides added the same content, but only one of them with a
+        // terminating newline
+        assert_eq!(
+            plain_merge(&conflict([b"a\n", b"", b"a"])),
+            conflict([b"a\n", b"", b"a"])
+        );
+        assert_eq!(merge(&conflict([b"a\n", b"", b"a"])), resolved(b"a\n"));
+        // One side removed the terminating newline, the other side changed the
+        // last line
+        assert_eq!(
+            plain_merge(&conflict([b"a\nb", b"a\nb\n", b"a\nc\n"])),
+            conflict([b"a\nb", b"a\nb\n", b"a\nc\n"])
+        );
+        assert_eq!(
+            merge(&conflict([b"a\nb", b"a\nb\n", b"a\nc\n"])),
+            resolved(b"a\nc")
+        );
+        // One side added the terminating newline, the other side changed the
+        // first line
+        assert_eq!(
+            merge(&conflict([b"a\nb\n", b"a\nb", b"c\nb"])),
+            resolved(b"c\nb\n")
+        );
+        // Conflicts in the content are kept
+        assert_eq!(
+            merge(&conflict([b"a\nb", b"a\nc\n", b"a\nd\n"])),
+            conflict([b"a\nb", b"a\nc\n", b"a\nd\n"])
+        );
+    }
+
     #[test]
     fn test_merge_hunk_by_word() {
         let options = MergeOptions {
             hunk_level: FileMergeHunkLevel::Word,
             same_change: SameChange::Accept,
+            ignore_trailing_newline: false,
         };
         let merge = |inputs: &_| merge(inputs, &options);
         // No context line in between, but "\n" is a context word
@@ -1106,4 +1230,55 @@ mod tests {
             conflict([b"a b", b"a", b"x a c"])
         );
     }
+
+    #[test]
+    fn test_merge_with_escalation() {
+        let options = MergeOptions {
+            hunk_level: FileMergeHunkLevel::Line,
+            same_change: SameChange::Accept,
+            ignore_trailing_newline: false,
+        };
+        let levels = [FileMergeHunkLevel::Line, FileMergeHunkLevel::Word];
+
+        // Conflicts at the line level, but resolves at the word level
+        let inputs = conflict([b"a b\n", b"a\n", b"c a\n"]);
+        assert_eq!(
+            merge_hunks(&inputs, &options),
+            MergeResult::Conflict(vec![conflict([b"a b\n", b"a\n", b"c a\n"])])
+        );
+        assert_eq!(
+            merge_with_escalation(&inputs, &options, &levels),
+            MergeResult::Resolved(hunk(b"c a b\n"))
+        );
+
+        // The first level which resolves wins
+        let inputs = conflict([b"a\nb\n", b"a\n", b"c\na\n"]);
+        assert_eq!(
+            merge_with_escalation(&inputs, &options, &levels),
+            MergeResult::Resolved(hunk(b"c\na\nb\n"))
+        );
+
+        // Conflicts at all levels: the word-level hunks are returned
+        let inputs = conflict([b"x\na b\n", b"x\na\n", b"y\na c\n"]);
+        assert_matches!(
+            merge_with_escalation(&inputs, &options, &levels),
+            MergeResult::Conflict(_)
+        );
+        assert_eq!(
+            merge_with_escalation(&inputs, &options, &levels),
+            merge_hunks(
+                &inputs,
+                &MergeOptions {
+                    hunk_level: FileMergeHunkLevel::Word,
+                    ..options.clone()
+                }
+            )
+        );
+
+        // Without levels, the hunk level of the options is used
+        assert_eq!(
+            merge_with_escalation(&inputs, &options, &[]),
+            merge_hunks(&inputs, &options)
+        );
+    }
 }
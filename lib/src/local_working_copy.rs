@@ -2020,9 +2020,11 @@ impl TreeState {
                     let conflict_marker_len =
                         choose_materialized_conflict_marker_len(&file.contents);
                     let options = ConflictMaterializeOptions {
-                        marker_style: self.conflict_marker_style,
                         marker_len: Some(conflict_marker_len),
-                        merge: self.store.merge_options().clone(),
+                        ..ConflictMaterializeOptions::new(
+                            self.conflict_marker_style,
+                            self.store.merge_options().clone(),
+                        )
                     };
                     let contents = materialize_merge_result_to_bytes(&file.contents, &options);
                     let mut file_state = self
@@ -56,6 +56,9 @@ pub struct MergeOptions {
     pub hunk_level: FileMergeHunkLevel,
     /// Whether to resolve conflict that makes the same change at all sides.
     pub same_change: SameChange,
+    /// Whether to resolve differences in the terminating newline separately
+    /// from the rest of the file content.
+    pub ignore_trailing_newline: bool,
 }
 
 impl MergeOptions {
@@ -66,6 +69,7 @@ impl MergeOptions {
             // needed. It wouldn't be translated to FileMergeHunkLevel.
             hunk_level: settings.get("merge.hunk-level")?,
             same_change: settings.get("merge.same-change")?,
+            ignore_trailing_newline: settings.get("merge.ignore-trailing-newline")?,
         })
     }
 }
@@ -12,19 +12,89 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use bstr::BString;
+use bstr::ByteSlice as _;
 use indoc::indoc;
 use itertools::Itertools as _;
+use jj_lib::backend::BackendError;
+use jj_lib::backend::CopyId;
 use jj_lib::backend::FileId;
+use jj_lib::backend::TreeValue;
+use jj_lib::conflicts::AuditFinding;
+use jj_lib::conflicts::AuditFindingKind;
+use jj_lib::conflicts::ConflictFormatVersion;
 use jj_lib::conflicts::ConflictMarkerStyle;
+use jj_lib::conflicts::ConflictMaterializeError;
 use jj_lib::conflicts::ConflictMaterializeOptions;
+use jj_lib::conflicts::ConflictParseOptions;
+use jj_lib::conflicts::ConflictStats;
+use jj_lib::conflicts::DEFAULT_CONFLICT_INSTRUCTIONS;
+use jj_lib::conflicts::DiffBaseStrategy;
+use jj_lib::conflicts::HeuristicConfig;
+use jj_lib::conflicts::HunkChoice;
+use jj_lib::conflicts::HunkDecodeError;
+use jj_lib::conflicts::LineOp;
+use jj_lib::conflicts::LintWarning;
+use jj_lib::conflicts::LintWarningKind;
 use jj_lib::conflicts::MIN_CONFLICT_MARKER_LEN;
+use jj_lib::conflicts::MarkerError;
+use jj_lib::conflicts::MarkerErrorKind;
+use jj_lib::conflicts::MergeCommand;
+use jj_lib::conflicts::NonFileConflictMode;
+use jj_lib::conflicts::ResolveCounts;
+use jj_lib::conflicts::SemanticMerger;
+use jj_lib::conflicts::SideSelector;
+use jj_lib::conflicts::apply_edit_script;
+use jj_lib::conflicts::assert_roundtrip_stable;
+use jj_lib::conflicts::audit_conflicts;
 use jj_lib::conflicts::choose_materialized_conflict_marker_len;
+use jj_lib::conflicts::conflict_stats;
+use jj_lib::conflicts::conflict_summary_line;
+use jj_lib::conflicts::conflicting_ranges_per_side;
+use jj_lib::conflicts::decode_hunks;
+use jj_lib::conflicts::detect_conflict_format_version;
+use jj_lib::conflicts::duplicate_sides;
+use jj_lib::conflicts::encode_hunks;
+use jj_lib::conflicts::estimate_materialize_memory;
 use jj_lib::conflicts::extract_as_single_hunk;
+use jj_lib::conflicts::hunk_fingerprint;
+use jj_lib::conflicts::is_reorder_only;
+use jj_lib::conflicts::lint_resolution;
+use jj_lib::conflicts::materialize_conflict;
+use jj_lib::conflicts::materialize_conflict_side_by_side;
+use jj_lib::conflicts::materialize_full_versions;
+use jj_lib::conflicts::materialize_merge_result_structured;
 use jj_lib::conflicts::materialize_merge_result_to_bytes;
+use jj_lib::conflicts::materialize_merge_result_with_timings;
+use jj_lib::conflicts::merge_and_materialize;
 use jj_lib::conflicts::parse_conflict;
+use jj_lib::conflicts::parse_conflict_with_options;
+use jj_lib::conflicts::parse_conflict_with_version;
+use jj_lib::conflicts::parse_yaml_block_scalar;
+use jj_lib::conflicts::partial_resolution;
+use jj_lib::conflicts::reassemble_conflict;
+use jj_lib::conflicts::renormalize_markers;
+use jj_lib::conflicts::required_marker_length;
+use jj_lib::conflicts::resolution_churn;
+use jj_lib::conflicts::resolve_inferred;
+use jj_lib::conflicts::resolve_to_tree_value;
+use jj_lib::conflicts::resolve_tree;
+use jj_lib::conflicts::resolve_with_command;
+use jj_lib::conflicts::restore_conflict_materialization;
+use jj_lib::conflicts::semantic_resolve;
+use jj_lib::conflicts::simplify_conflict;
+use jj_lib::conflicts::to_yaml_block_scalar;
 use jj_lib::conflicts::update_from_content;
+use jj_lib::conflicts::update_from_content_with_counts;
+use jj_lib::conflicts::validate_markers;
+use jj_lib::files;
 use jj_lib::files::FileMergeHunkLevel;
+use jj_lib::files::MergeResult;
 use jj_lib::merge::Merge;
+use jj_lib::merge::MergedTreeValue;
 use jj_lib::merge::SameChange;
 use jj_lib::repo::Repo as _;
 use jj_lib::repo_path::RepoPath;
@@ -2401,22 +2471,3288 @@ fn test_update_from_content_malformed_conflict() {
     assert_eq!(second_snapshot, new_conflict);
 }
 
-fn materialize_conflict_string(
-    store: &Store,
-    path: &RepoPath,
-    conflict: &Merge<Option<FileId>>,
-    marker_style: ConflictMarkerStyle,
-) -> String {
-    let contents = extract_as_single_hunk(conflict, store, path)
+#[test]
+fn test_conflicting_ranges_per_side() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "a\nb\nc\nd\ne\n");
+    let left_id = testutils::write_file(store, path, "a\nleft b\nc\nd\ne\n");
+    let right_id = testutils::write_file(store, path, "a\nb\nc\nright d.1\nright d.2\n");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id.clone())],
+    );
+    let ranges = conflicting_ranges_per_side(store, path, &conflict)
         .block_on()
         .unwrap();
-    let options = ConflictMaterializeOptions {
-        marker_style,
-        marker_len: None,
-        merge: MergeOptions {
-            hunk_level: FileMergeHunkLevel::Line,
-            same_change: SameChange::Accept,
-        },
+    assert_eq!(ranges, vec![vec![1..2], vec![3..5]]);
+
+    // A side which only deletes lines has an empty range at the deletion point
+    let delete_id = testutils::write_file(store, path, "a\nb\ne\n");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(delete_id.clone()), Some(left_id.clone())],
+    );
+    let ranges = conflicting_ranges_per_side(store, path, &conflict)
+        .block_on()
+        .unwrap();
+    assert_eq!(ranges, vec![vec![2..2], vec![1..2]]);
+}
+
+#[test]
+fn test_materialize_parse_comment_banner() {
+    // Lines which look like shell or Python comment banners are content
+    let banner = "########## Section banner\n";
+    let merge = Merge::from_vec(vec![
+        format!("{banner}keep\nleft\n"),
+        format!("{banner}keep\nbase\n"),
+        format!("{banner}keep\nright\n"),
+    ]);
+    assert_eq!(
+        choose_materialized_conflict_marker_len(&merge),
+        MIN_CONFLICT_MARKER_LEN
+    );
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let materialized = materialize_merge_result_to_bytes(&merge, &options);
+    insta::assert_snapshot!(materialized, @r"
+    ########## Section banner
+    keep
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -base
+    +left
+    +++++++ Contents of side #2
+    right
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&merge, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(
+        parse_conflict(&materialized, 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+
+    // Lines which look like comment lines make the conflict markers longer, so
+    // they aren't ignored when parsing
+    let merge = merge.map(|term| term.replace("#", "@"));
+    let marker_len = choose_materialized_conflict_marker_len(&merge);
+    assert_eq!(marker_len, 14);
+    let materialized = materialize_merge_result_to_bytes(&merge, &options);
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&merge, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(parse_conflict(&materialized, 2, marker_len), Some(hunks));
+}
+
+#[test]
+fn test_materialize_conflict_manifest() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\nline 4");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nleft 3\nline 4");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nright 3\nline 4");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id.clone())],
+    );
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.manifest = true;
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    <<<<<<< Conflict 1 of 2
+    %%%%%%% Changes from base to side #1
+    -line 1
+    +left 1
+    +++++++ Contents of side #2
+    right 1
+    >>>>>>> Conflict 1 of 2 ends
+    line 2
+    <<<<<<< Conflict 2 of 2
+    %%%%%%% Changes from base to side #1
+    -line 3
+    +left 3
+    +++++++ Contents of side #2
+    right 3
+    >>>>>>> Conflict 2 of 2 ends
+    line 4
+    @@@@@@@ Conflict manifest (no terminating newline)
+    @@@@@@@ Conflict 1 of 2: 91d6fcbd6aea
+    @@@@@@@ Conflict 2 of 2: c53d7a1edf8d
+    ");
+
+    // The manifest lists one fingerprint per conflict hunk
+    let plain = materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+    let hunks = parse_conflict(plain.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN).unwrap();
+    let fingerprints = hunks
+        .iter()
+        .filter(|hunk| !hunk.is_resolved())
+        .map(hunk_fingerprint)
+        .collect_vec();
+    assert_eq!(fingerprints.len(), 2);
+    for (index, fingerprint) in fingerprints.iter().enumerate() {
+        assert!(materialized.contains(&format!("Conflict {} of 2: {fingerprint}\n", index + 1)));
+    }
+
+    // The manifest is ignored when parsing, including the newline inserted
+    // before it
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+    // Other comment lines noting a missing newline don't affect the preceding
+    // content
+    let edited = format!("resolved\n@@@@@@@ Note (no terminating newline)\n{materialized}");
+    assert_eq!(
+        parse_conflict(edited.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN).unwrap()[0],
+        Merge::resolved("resolved\n".into())
+    );
+
+    // The conflict is unchanged when updated from the materialized content
+    let updated = update_from_content(
+        &conflict,
+        store,
+        path,
+        materialized.as_bytes(),
+        MIN_CONFLICT_MARKER_LEN,
+    )
+    .block_on()
+    .unwrap();
+    assert_eq!(updated, conflict);
+
+    // The manifest isn't written to the sides of a partially resolved conflict
+    let edited = materialized.replacen(
+        indoc! {"
+            <<<<<<< Conflict 1 of 2
+            %%%%%%% Changes from base to side #1
+            -line 1
+            +left 1
+            +++++++ Contents of side #2
+            right 1
+            >>>>>>> Conflict 1 of 2 ends
+        "},
+        "resolved 1\n",
+        1,
+    );
+    let updated = update_from_content(
+        &conflict,
+        store,
+        path,
+        edited.as_bytes(),
+        MIN_CONFLICT_MARKER_LEN,
+    )
+    .block_on()
+    .unwrap();
+    let new_base_id = testutils::write_file(store, path, "resolved 1\nline 2\nline 3\nline 4");
+    let new_left_id = testutils::write_file(store, path, "resolved 1\nline 2\nleft 3\nline 4");
+    let new_right_id = testutils::write_file(store, path, "resolved 1\nline 2\nright 3\nline 4");
+    assert_eq!(
+        updated,
+        Merge::from_removes_adds(
+            vec![Some(new_base_id)],
+            vec![Some(new_left_id), Some(new_right_id)]
+        )
+    );
+
+    // The manifest is removed if all conflicts are resolved, along with the
+    // newline inserted before it
+    let resolved = indoc! {"
+        resolved 1
+        line 2
+        resolved 3
+        line 4
+        @@@@@@@ Conflict manifest (no terminating newline)
+        @@@@@@@ Conflict 1 of 2: 91d6fcbd6aea
+        @@@@@@@ Conflict 2 of 2: c53d7a1edf8d
+    "};
+    let updated = update_from_content(
+        &conflict,
+        store,
+        path,
+        resolved.as_bytes(),
+        MIN_CONFLICT_MARKER_LEN,
+    )
+    .block_on()
+    .unwrap();
+    let resolved_id = testutils::write_file(store, path, "resolved 1\nline 2\nresolved 3\nline 4");
+    assert_eq!(updated, Merge::normal(resolved_id));
+}
+
+#[test]
+fn test_renormalize_markers() {
+    let content = indoc! {"
+        line 1
+        <<<<<<<<< Conflict 1 of 1
+        %%%%%%%%% Changes from base to side #1
+        -line 2
+        +left 2
+        +++++++++ Contents of side #2
+        right 2
+        >>>>>>>>> Conflict 1 of 1 ends
+        line 3
+    "};
+    let renormalized = renormalize_markers(content.as_bytes(), 7).unwrap();
+    insta::assert_snapshot!(String::from_utf8(renormalized.clone()).unwrap(), @r"
+    line 1
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -line 2
+    +left 2
+    +++++++ Contents of side #2
+    right 2
+    >>>>>>> Conflict 1 of 1 ends
+    line 3
+    ");
+    assert_eq!(
+        parse_conflict(&renormalized, 2, 7),
+        parse_conflict(content.as_bytes(), 2, 9)
+    );
+
+    // The conflict marker style is preserved
+    let content = indoc! {"
+        <<<<<<<<< Side #1
+        left
+        ||||||||| Base
+        base
+        =========
+        right
+        >>>>>>>>> Side #2
+    "};
+    insta::assert_snapshot!(
+        String::from_utf8(renormalize_markers(content.as_bytes(), 7).unwrap()).unwrap(),
+        @r"
+    <<<<<<< Side #1 (Conflict 1 of 1)
+    left
+    ||||||| Base
+    base
+    =======
+    right
+    >>>>>>> Side #2 (Conflict 1 of 1 ends)
+    "
+    );
+
+    // A side containing a line which looks like a conflict marker of length 7
+    // would collide with the shorter markers
+    let content = indoc! {"
+        <<<<<<<<< Conflict 1 of 1
+        +++++++++ Contents of side #1
+        left
+        +++++++
+        --------- Contents of base
+        base
+        +++++++++ Contents of side #2
+        right
+        >>>>>>>>> Conflict 1 of 1 ends
+    "};
+    assert!(parse_conflict(content.as_bytes(), 2, 9).is_some());
+    assert_eq!(renormalize_markers(content.as_bytes(), 7), None);
+    // But lengthening the markers is fine
+    assert!(renormalize_markers(content.as_bytes(), 11).is_some());
+
+    // No conflicts to rewrite
+    assert_eq!(renormalize_markers(b"line 1\nline 2\n", 7), None);
+
+    // Markers shorter than the minimum length wouldn't be parsed as markers
+    let content = indoc! {"
+        <<<<<<< Conflict 1 of 1
+        +++++++ Contents of side #1
+        left
+        ------- Contents of base
+        base
+        +++++++ Contents of side #2
+        right
+        >>>>>>> Conflict 1 of 1 ends
+    "};
+    assert_eq!(renormalize_markers(content.as_bytes(), 5), None);
+    assert_eq!(renormalize_markers(content.as_bytes(), 0), None);
+}
+
+#[test]
+fn test_conflict_summary_line() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("dir/file.rs");
+    let file_value = |contents: &str| TreeValue::File {
+        id: testutils::write_file(store, path, contents),
+        executable: false,
+        copy_id: CopyId::placeholder(),
+    };
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("left 1\nline 2\nleft 3\n")),
+        Some(file_value("line 1\nline 2\nline 3\n")),
+        Some(file_value("right 1\nline 2\nright 3\n")),
+        Some(file_value("line 1\nline 2\nline 3\n")),
+        Some(file_value("line 1\nline 2\nother 3\n")),
+    ]);
+    let stats = conflict_stats(store, path, &conflict.to_file_merge().unwrap())
+        .block_on()
+        .unwrap();
+    assert_eq!(
+        stats,
+        ConflictStats {
+            num_conflict_hunks: 2,
+            num_sides: 3,
+        }
+    );
+    assert_eq!(
+        conflict_summary_line(store, path, &conflict)
+            .block_on()
+            .unwrap(),
+        "dir/file.rs: 2 conflicting hunks across 3 sides"
+    );
+
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("left 1\nline 2\n")),
+        Some(file_value("line 1\nline 2\n")),
+        Some(file_value("right 1\nline 2\n")),
+    ]);
+    assert_eq!(
+        conflict_summary_line(store, path, &conflict)
+            .block_on()
+            .unwrap(),
+        "dir/file.rs: 1 conflicting hunk across 2 sides"
+    );
+
+    // Mixed-type conflict
+    let symlink_id = store.write_symlink(path, "target").block_on().unwrap();
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("left\n")),
+        Some(file_value("base\n")),
+        Some(TreeValue::Symlink(symlink_id)),
+    ]);
+    assert_eq!(
+        conflict_summary_line(store, path, &conflict)
+            .block_on()
+            .unwrap(),
+        "dir/file.rs: conflict between file, symlink across 2 sides"
+    );
+}
+
+#[test]
+fn test_resolve_inferred() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let resolve = |content: &str| {
+        resolve_inferred(store, path, content.as_bytes())
+            .block_on()
+            .unwrap()
+    };
+    let file_value = |contents: &str| TreeValue::File {
+        id: testutils::write_file(store, path, contents),
+        executable: false,
+        copy_id: CopyId::placeholder(),
     };
-    String::from_utf8(materialize_merge_result_to_bytes(&contents, &options).into()).unwrap()
+
+    // The user edited the sides so that they no longer conflict. There's no
+    // stored conflict to tell the arity or the marker length.
+    let content = indoc! {"
+        line 1
+        <<<<<<<<< Conflict 1 of 1
+        %%%%%%%%% Changes from base to side #1
+        -line 2
+        +left 2
+         line 3
+         line 4
+        +++++++++ Contents of side #2
+        line 2
+        line 3
+        right 4
+        >>>>>>>>> Conflict 1 of 1 ends
+        line 5
+    "};
+    assert_eq!(
+        resolve(content),
+        Some(file_value("line 1\nleft 2\nline 3\nright 4\nline 5\n"))
+    );
+
+    // 3-sided conflict
+    let content = indoc! {"
+        <<<<<<< Conflict 1 of 1
+        +++++++ Contents of side #1
+        a
+        ------- Contents of base #1
+        b
+        +++++++ Contents of side #2
+        b
+        ------- Contents of base #2
+        c
+        +++++++ Contents of side #3
+        c
+        >>>>>>> Conflict 1 of 1 ends
+    "};
+    assert_eq!(resolve(content), Some(file_value("a\n")));
+
+    // The conflict remains
+    let content = indoc! {"
+        <<<<<<< Conflict 1 of 1
+        %%%%%%% Changes from base to side #1
+        -line 1
+        +left 1
+        +++++++ Contents of side #2
+        right 1
+        >>>>>>> Conflict 1 of 1 ends
+    "};
+    assert_eq!(resolve(content), None);
+
+    // No conflict markers
+    assert_eq!(resolve("resolved\n"), Some(file_value("resolved\n")));
+
+    // A conflict which is never closed isn't resolved as content
+    let content = indoc! {"
+        line 1
+        <<<<<<< Conflict 1 of 1
+        +++++++ Contents of side #1
+        left 1
+        ------- Contents of base
+        line 1
+    "};
+    assert_eq!(resolve(content), None);
+
+    // A conflict which can't be parsed isn't resolved as content either
+    let content = indoc! {"
+        <<<<<<< Conflict 1 of 1
+        >>>>>>> Conflict 1 of 1 ends
+    "};
+    assert_eq!(resolve(content), None);
+}
+
+#[test]
+fn test_materialize_conflict_context() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    // Returns the last line opening a block which isn't closed yet
+    fn enclosing_block(content_before: &[u8]) -> Option<Vec<u8>> {
+        let mut open_lines = vec![];
+        for line in content_before.lines() {
+            for &b in line {
+                match b {
+                    b'{' => open_lines.push(line),
+                    b'}' => {
+                        open_lines.pop();
+                    }
+                    _ => {}
+                }
+            }
+        }
+        open_lines.last().map(|line| line.to_vec())
+    }
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            fn foo() {
+                let x = 1;
+            }
+
+            fn bar() {
+                if x {
+                    let y = 2;
+                }
+            }
+        "},
+    );
+    let left_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            fn foo() {
+                let x = 3;
+            }
+
+            fn bar() {
+                if x {
+                    let y = 4;
+                }
+            }
+        "},
+    );
+    let right_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            fn foo() {
+                let x = 5;
+            }
+
+            fn bar() {
+                if x {
+                    let y = 6;
+                }
+            }
+        "},
+    );
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id.clone())],
+    );
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.context = Some(enclosing_block);
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    fn foo() {
+    @@@@@@@ fn foo() {
+    <<<<<<< Conflict 1 of 2
+    %%%%%%% Changes from base to side #1
+    -    let x = 1;
+    +    let x = 3;
+    +++++++ Contents of side #2
+        let x = 5;
+    >>>>>>> Conflict 1 of 2 ends
+    }
+
+    fn bar() {
+        if x {
+    @@@@@@@     if x {
+    <<<<<<< Conflict 2 of 2
+    %%%%%%% Changes from base to side #1
+    -        let y = 2;
+    +        let y = 4;
+    +++++++ Contents of side #2
+            let y = 6;
+    >>>>>>> Conflict 2 of 2 ends
+        }
+    }
+    ");
+
+    // The context lines are ignored when parsing
+    let plain = materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        parse_conflict(plain.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN)
+    );
+
+    // The context lines aren't written to the file when updating the conflict
+    let update = |content: &str| {
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+    let resolved = indoc! {"
+        fn foo() {
+        @@@@@@@ fn foo() {
+            let x = 3;
+        }
+
+        fn bar() {
+            if x {
+        @@@@@@@     if x {
+                let y = 6;
+            }
+        }
+    "};
+    let resolved_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            fn foo() {
+                let x = 3;
+            }
+
+            fn bar() {
+                if x {
+                    let y = 6;
+                }
+            }
+        "},
+    );
+    assert_eq!(update(resolved), Merge::normal(resolved_id));
+}
+
+#[test]
+fn test_resolve_tree() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path1 = repo_path("file1");
+    let path2 = repo_path("file2");
+    let make_conflict = |path| {
+        Merge::from_removes_adds(
+            vec![Some(testutils::write_file(
+                store,
+                path,
+                "line 1\nline 2\nline 3\n",
+            ))],
+            vec![
+                Some(testutils::write_file(
+                    store,
+                    path,
+                    "left 1\nline 2\nleft 3\n",
+                )),
+                Some(testutils::write_file(
+                    store,
+                    path,
+                    "right 1\nline 2\nright 3\n",
+                )),
+            ],
+        )
+    };
+    let conflict1 = make_conflict(path1);
+    let conflict2 = make_conflict(path2);
+    let choices = HashMap::from([
+        (
+            path1.to_owned(),
+            (
+                conflict1.clone(),
+                vec![HunkChoice::Side(1), HunkChoice::Base(0)],
+            ),
+        ),
+        (
+            path2.to_owned(),
+            (
+                conflict2.clone(),
+                vec![HunkChoice::Keep, HunkChoice::Side(0)],
+            ),
+        ),
+    ]);
+    let resolved = resolve_tree(store, &choices).block_on().unwrap();
+    assert_eq!(resolved.len(), 2);
+
+    // All hunks of the first file were resolved
+    assert_eq!(
+        resolved[path1],
+        Merge::normal(testutils::write_file(
+            store,
+            path1,
+            "right 1\nline 2\nline 3\n"
+        ))
+    );
+
+    // The first hunk of the second file is still conflicted
+    assert_eq!(
+        resolved[path2],
+        Merge::from_removes_adds(
+            vec![Some(testutils::write_file(
+                store,
+                path2,
+                "line 1\nline 2\nleft 3\n"
+            ))],
+            vec![
+                Some(testutils::write_file(
+                    store,
+                    path2,
+                    "left 1\nline 2\nleft 3\n"
+                )),
+                Some(testutils::write_file(
+                    store,
+                    path2,
+                    "right 1\nline 2\nleft 3\n"
+                )),
+            ]
+        )
+    );
+
+    // Choices which don't match the conflict are rejected
+    let choices = HashMap::from([(
+        path1.to_owned(),
+        (conflict1.clone(), vec![HunkChoice::Side(2)]),
+    )]);
+    assert!(resolve_tree(store, &choices).block_on().is_err());
+    let choices = HashMap::from([(path1.to_owned(), (conflict1, vec![HunkChoice::Keep; 3]))]);
+    assert!(resolve_tree(store, &choices).block_on().is_err());
+}
+
+#[test]
+fn test_materialize_structured_identical_adjacent_hunks() {
+    let base = "a\n1\na\n2\na\n";
+    let left = "b\n1\nb\n2\nd\n";
+    let right = "c\n1\nc\n2\nc\n";
+    let merge = Merge::from_vec(vec![left, base, right]);
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let result = materialize_merge_result_structured(&merge, &options);
+    assert_eq!(
+        result.content,
+        materialize_merge_result_to_bytes(&merge, &options)
+    );
+    let flags = result
+        .conflicts
+        .iter()
+        .map(|conflict| conflict.same_as_previous)
+        .collect_vec();
+    // The first two hunks are identical, the third one differs
+    assert_eq!(flags, [false, true, false]);
+    assert_eq!(
+        result.conflicts[0].fingerprint,
+        result.conflicts[1].fingerprint
+    );
+    assert_ne!(
+        result.conflicts[1].fingerprint,
+        result.conflicts[2].fingerprint
+    );
+
+    // Resolved content has no conflict hunks
+    let merge = Merge::from_vec(vec![left, base, base]);
+    let result = materialize_merge_result_structured(&merge, &options);
+    assert_eq!(result.content, left);
+    assert!(result.conflicts.is_empty());
+}
+
+#[test]
+fn test_merge_and_materialize() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nleft 3\n");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nline 3\n");
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id)],
+    );
+    let (merge_result, content) = merge_and_materialize(store, path, &conflict, &options)
+        .block_on()
+        .unwrap();
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    assert_eq!(merge_result, files::merge_hunks(&contents, &options.merge));
+    assert!(matches!(merge_result, MergeResult::Conflict(_)));
+    assert_eq!(
+        content.to_str().unwrap(),
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff)
+    );
+
+    // Resolvable conflicts are materialized as the resolved content
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id), Some(base_id)],
+    );
+    let (merge_result, content) = merge_and_materialize(store, path, &conflict, &options)
+        .block_on()
+        .unwrap();
+    assert_eq!(
+        merge_result,
+        MergeResult::Resolved("left 1\nline 2\nleft 3\n".into())
+    );
+    assert_eq!(content, b"left 1\nline 2\nleft 3\n");
+}
+
+#[test]
+fn test_materialize_conflict_ignoring_line_endings_in_diff() {
+    let base = "line 1\nline 2\nline 3\n";
+    let left = "line 1\r\nline 2\r\nline 3\r\n";
+    let right = "line 1\nchanged\nline 3\n";
+    let merge = Merge::from_vec(vec![left, base, right]);
+    let options = ConflictMaterializeOptions {
+        diff_ignore_line_endings: true,
+        ..materialize_options(ConflictMarkerStyle::Diff)
+    };
+    let materialized = materialize_merge_result_to_bytes(&merge, &options);
+    // The lines which only differ in line endings are written as context lines
+    // with the line endings of the side
+    insta::assert_snapshot!(materialized.to_str().unwrap().replace('\r', "<CR>"), @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+     line 1<CR>
+     line 2<CR>
+     line 3<CR>
+    +++++++ Contents of side #2
+    line 1
+    changed
+    line 3
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+    // The sides are parsed unchanged, but the base takes the line endings of the
+    // side
+    assert_eq!(
+        parse_conflict(&materialized, 2, MIN_CONFLICT_MARKER_LEN),
+        Some(vec![Merge::from_vec(vec![
+            BString::from(left),
+            BString::from(left),
+            BString::from(right),
+        ])])
+    );
+
+    // Without the option, every line of the first side differs from the base
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let materialized = materialize_merge_result_to_bytes(&merge, &options);
+    insta::assert_snapshot!(materialized.to_str().unwrap().replace('\r', "<CR>"), @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1
+    line 1<CR>
+    line 2<CR>
+    line 3<CR>
+    %%%%%%% Changes from base to side #2
+     line 1
+    -line 2
+    +changed
+     line 3
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+}
+
+#[test]
+fn test_restore_conflict_materialization() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nline 3\n");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nline 3\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let original = materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+
+    // Resolve the conflict
+    let resolved = update_from_content(
+        &conflict,
+        store,
+        path,
+        b"merged 1\nline 2\nline 3\n",
+        MIN_CONFLICT_MARKER_LEN,
+    )
+    .block_on()
+    .unwrap();
+    assert!(resolved.is_resolved());
+
+    // Undo the resolution
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let restored = restore_conflict_materialization(store, path, &conflict, &options)
+        .block_on()
+        .unwrap();
+    assert_eq!(restored.to_str().unwrap(), original);
+    insta::assert_snapshot!(restored.to_str().unwrap(), @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -line 1
+    +left 1
+    +++++++ Contents of side #2
+    right 1
+    >>>>>>> Conflict 1 of 1 ends
+    line 2
+    line 3
+    ");
+    assert_eq!(
+        update_from_content(&conflict, store, path, &restored, MIN_CONFLICT_MARKER_LEN)
+            .block_on()
+            .unwrap(),
+        conflict
+    );
+}
+
+#[test]
+fn test_materialize_conflict_heuristic_config() {
+    // Both sides are equally close to the base, so either could be chosen as
+    // the snapshot
+    let merge = Merge::from_vec(vec!["one\n", "base\n", "two\n"]);
+    let materialize = |seed| {
+        let options = ConflictMaterializeOptions {
+            heuristics: HeuristicConfig {
+                seed,
+                ..Default::default()
+            },
+            ..materialize_options(ConflictMarkerStyle::Diff)
+        };
+        materialize_merge_result_to_bytes(&merge, &options)
+    };
+    insta::assert_snapshot!(materialize(None), @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -base
+    +one
+    +++++++ Contents of side #2
+    two
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+    // Depending on the seed, the other side may be chosen
+    insta::assert_snapshot!(materialize(Some(1)), @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1
+    one
+    %%%%%%% Changes from base to side #2
+    -base
+    +two
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    for seed in [None, Some(0), Some(1), Some(2)] {
+        let materialized = materialize(seed);
+        // The same config produces the same output
+        assert_eq!(materialized, materialize(seed));
+        // The chosen formatting doesn't affect the parsed conflict
+        let hunks = parse_conflict(&materialized, 2, MIN_CONFLICT_MARKER_LEN).unwrap();
+        assert_eq!(hunks, [merge.map(|term| term.as_bytes().into())]);
+    }
+}
+
+#[test]
+fn test_resolution_churn() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nline 3\n");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nright 3\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let churn = |resolved: &str, baseline| {
+        resolution_churn(store, path, &conflict, resolved.as_bytes(), baseline)
+            .block_on()
+            .unwrap()
+    };
+
+    // Taking side B as is doesn't introduce any churn
+    assert_eq!(
+        churn("right 1\nline 2\nright 3\n", SideSelector::Side(1)),
+        0
+    );
+    // Editing one line of side B counts the removed and the added line
+    assert_eq!(
+        churn("right 1\nline 2\nedited 3\n", SideSelector::Side(1)),
+        2
+    );
+    // Compared to side A, both changed lines count
+    assert_eq!(
+        churn("right 1\nline 2\nedited 3\n", SideSelector::Side(0)),
+        4
+    );
+    assert_eq!(
+        churn("right 1\nline 2\nedited 3\n", SideSelector::Base(0)),
+        4
+    );
+
+    // The baseline must exist in the conflict
+    assert!(
+        resolution_churn(store, path, &conflict, b"", SideSelector::Side(2))
+            .block_on()
+            .is_err()
+    );
+}
+
+#[test]
+fn test_parse_conflict_with_diff_like_resolved_content() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    // Example diff output in the resolved regions around the conflict
+    let path = repo_path("file");
+    let base_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            Example:
+            -removed
+            +added
+             context
+            line
+            +after
+            -example
+        "},
+    );
+    let left_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            Example:
+            -removed
+            +added
+             context
+            left
+            +after
+            -example
+        "},
+    );
+    let right_id = testutils::write_file(
+        store,
+        path,
+        indoc! {"
+            Example:
+            -removed
+            +added
+             context
+            right
+            +after
+            -example
+        "},
+    );
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+
+    for marker_style in [
+        ConflictMarkerStyle::Diff,
+        ConflictMarkerStyle::DiffExperimental,
+        ConflictMarkerStyle::Snapshot,
+        ConflictMarkerStyle::Git,
+    ] {
+        let materialized = materialize_conflict_string(store, path, &conflict, marker_style);
+        let hunks = parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN).unwrap();
+        // The resolved regions are preserved byte for byte
+        assert_eq!(
+            hunks,
+            [
+                Merge::resolved("Example:\n-removed\n+added\n context\n".into()),
+                Merge::from_vec(vec!["left\n".into(), "line\n".into(), "right\n".into()]),
+                Merge::resolved("+after\n-example\n".into()),
+            ]
+        );
+        assert_eq!(
+            update_from_content(
+                &conflict,
+                store,
+                path,
+                materialized.as_bytes(),
+                MIN_CONFLICT_MARKER_LEN
+            )
+            .block_on()
+            .unwrap(),
+            conflict
+        );
+    }
+    insta::assert_snapshot!(
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff),
+        @r"
+    Example:
+    -removed
+    +added
+     context
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -line
+    +left
+    +++++++ Contents of side #2
+    right
+    >>>>>>> Conflict 1 of 1 ends
+    +after
+    -example
+    ");
+}
+
+#[test]
+fn test_materialize_conflict_strict() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "base\n");
+    let left_id = testutils::write_file(store, path, "left\n");
+    let symlink_id = store.write_symlink(path, "target").block_on().unwrap();
+    let file_value = |id: &FileId| {
+        Some(TreeValue::File {
+            id: id.clone(),
+            executable: false,
+            copy_id: CopyId::placeholder(),
+        })
+    };
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let materialize = |conflict, mode| {
+        let mut output = vec![];
+        let result =
+            materialize_conflict(store, path, conflict, &options, mode, &mut output).block_on();
+        (result, output)
+    };
+
+    // A symlink conflict is described by default
+    let conflict = Merge::from_vec(vec![
+        file_value(&left_id),
+        file_value(&base_id),
+        Some(TreeValue::Symlink(symlink_id)),
+    ]);
+    let (result, output) = materialize(&conflict, NonFileConflictMode::Describe);
+    result.unwrap();
+    assert_eq!(output, conflict.describe().as_bytes());
+
+    // In strict mode, nothing is written
+    let (result, output) = materialize(&conflict, NonFileConflictMode::Strict);
+    assert_matches!(
+        result,
+        Err(ConflictMaterializeError::NonFileConflict { path: error_path }) if error_path.as_ref() == path
+    );
+    assert!(output.is_empty());
+
+    // File conflicts are materialized in either mode
+    let right_id = testutils::write_file(store, path, "right\n");
+    let conflict = Merge::from_vec(vec![
+        file_value(&left_id),
+        file_value(&base_id),
+        file_value(&right_id),
+    ]);
+    let (result, output) = materialize(&conflict, NonFileConflictMode::Strict);
+    result.unwrap();
+    assert_eq!(
+        output.to_str().unwrap(),
+        materialize_conflict_string(
+            store,
+            path,
+            &conflict.to_file_merge().unwrap(),
+            ConflictMarkerStyle::Diff
+        )
+    );
+}
+
+#[test]
+fn test_materialize_conflict_min_diff_quality() {
+    let options = ConflictMaterializeOptions {
+        heuristics: HeuristicConfig {
+            min_diff_quality: Some(0.5),
+            ..Default::default()
+        },
+        ..materialize_options(ConflictMarkerStyle::Diff)
+    };
+
+    // The sides share nothing with the base, so snapshots are written instead
+    // of diffs removing and adding every line
+    let merge = Merge::from_vec(vec![
+        "left 1\nleft 2\n",
+        "base 1\nbase 2\n",
+        "right 1\nright 2\n",
+    ]);
+    insta::assert_snapshot!(materialize_merge_result_to_bytes(&merge, &options), @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1
+    left 1
+    left 2
+    ------- Contents of base
+    base 1
+    base 2
+    +++++++ Contents of side #2
+    right 1
+    right 2
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // Diffs are still written if most of the content is shared
+    let merge = Merge::from_vec(vec![
+        "line 1\nleft 2\nline 3\n",
+        "line 1\nline 2\nline 3\n",
+        "right 1\nright 2\nright 3\n",
+    ]);
+    insta::assert_snapshot!(materialize_merge_result_to_bytes(&merge, &options), @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+     line 1
+    -line 2
+    +left 2
+     line 3
+    +++++++ Contents of side #2
+    right 1
+    right 2
+    right 3
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+}
+
+#[test]
+fn test_update_from_content_with_counts() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nleft 3\n");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nright 3\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let update = |content: &str| {
+        update_from_content_with_counts(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+
+    // Unchanged content resolves nothing
+    let materialized =
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+    let (file_ids, counts) = update(&materialized);
+    assert_eq!(file_ids, conflict);
+    assert_eq!(
+        counts,
+        ResolveCounts {
+            resolved: 0,
+            remaining: 2
+        }
+    );
+
+    // Resolve the first of the two conflicts
+    let (file_ids, counts) = update(indoc! {"
+        resolved 1
+        line 2
+        <<<<<<< Conflict 2 of 2
+        %%%%%%% Changes from base to side #1
+        -line 3
+        +left 3
+        +++++++ Contents of side #2
+        right 3
+        >>>>>>> Conflict 2 of 2 ends
+    "});
+    assert!(!file_ids.is_resolved());
+    assert_eq!(
+        counts,
+        ResolveCounts {
+            resolved: 1,
+            remaining: 1
+        }
+    );
+
+    // Resolve everything
+    let (file_ids, counts) = update("resolved 1\nline 2\nresolved 3\n");
+    assert!(file_ids.is_resolved());
+    assert_eq!(
+        counts,
+        ResolveCounts {
+            resolved: 2,
+            remaining: 0
+        }
+    );
+}
+
+#[test]
+fn test_materialize_parse_unprefixed_diff_context() {
+    let base = indoc! {"
+        shared
+          indented
+        -dash
+        +plus
+
+        base
+        shared
+    "};
+    let left = indoc! {"
+        shared
+          indented
+        -dash
+        +plus
+
+        left
+        shared
+    "};
+    let right = "right\n";
+    let merge = Merge::from_vec(vec![left, base, right]);
+    let options = ConflictMaterializeOptions {
+        unprefixed_diff_context: true,
+        ..materialize_options(ConflictMarkerStyle::Diff)
+    };
+    let materialized = materialize_merge_result_to_bytes(&merge, &options);
+    // Only the lines which would be ambiguous are prefixed
+    insta::assert_snapshot!(materialized, @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    shared
+       indented
+     -dash
+     +plus
+
+    -base
+    +left
+    shared
+    +++++++ Contents of side #2
+    right
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    let parse_options = ConflictParseOptions {
+        unprefixed_diff_context: true,
+    };
+    let hunks =
+        parse_conflict_with_options(&materialized, 2, MIN_CONFLICT_MARKER_LEN, &parse_options);
+    assert_eq!(hunks, Some(vec![merge.map(|term| term.as_bytes().into())]));
+
+    // The format isn't accepted without the parser option
+    assert_eq!(
+        parse_conflict(&materialized, 2, MIN_CONFLICT_MARKER_LEN),
+        None
+    );
+}
+
+#[test]
+fn test_audit_conflicts() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let file_value = |path: &RepoPath, content: &str| {
+        Some(TreeValue::File {
+            id: testutils::write_file(store, path, content),
+            executable: false,
+            copy_id: CopyId::placeholder(),
+        })
+    };
+    let real_path = repo_path("real");
+    let resolved_path = repo_path("resolved");
+    let identical_path = repo_path("identical");
+    let trivial_path = repo_path("trivial");
+    let clean_path = repo_path("clean");
+    let conflicts = vec![
+        // A real conflict
+        (
+            real_path.to_owned(),
+            Merge::from_vec(vec![
+                file_value(real_path, "left\n"),
+                file_value(real_path, "base\n"),
+                file_value(real_path, "right\n"),
+            ]),
+        ),
+        (
+            resolved_path.to_owned(),
+            Merge::normal(file_value(resolved_path, "resolved\n").unwrap()),
+        ),
+        (
+            identical_path.to_owned(),
+            Merge::from_vec(vec![
+                file_value(identical_path, "same\n"),
+                file_value(identical_path, "base\n"),
+                file_value(identical_path, "same\n"),
+            ]),
+        ),
+        (
+            trivial_path.to_owned(),
+            Merge::from_vec(vec![
+                file_value(trivial_path, "left\n"),
+                file_value(trivial_path, "base\n"),
+                file_value(trivial_path, "base\n"),
+            ]),
+        ),
+        // Changes to different lines can be merged
+        (
+            clean_path.to_owned(),
+            Merge::from_vec(vec![
+                file_value(clean_path, "left 1\nline 2\nline 3\n"),
+                file_value(clean_path, "line 1\nline 2\nline 3\n"),
+                file_value(clean_path, "line 1\nline 2\nright 3\n"),
+            ]),
+        ),
+    ];
+    let findings = audit_conflicts(store, &conflicts).block_on().unwrap();
+    let finding = |path: &RepoPath, kind| AuditFinding {
+        path: path.to_owned(),
+        kind,
+    };
+    assert_eq!(
+        findings,
+        vec![
+            finding(resolved_path, AuditFindingKind::NotConflicted),
+            finding(identical_path, AuditFindingKind::IdenticalSides),
+            finding(trivial_path, AuditFindingKind::TriviallyResolvable),
+            finding(clean_path, AuditFindingKind::CleanMerge),
+        ]
+    );
+}
+
+#[test]
+fn test_materialize_conflict_diff_base_strategy() {
+    let merge = Merge::from_vec(vec![
+        "x\ny\nc\n",
+        "a\nb\nc\n",
+        "a\ny\nc\n",
+        "a\ny\nz\n",
+        "p\nq\nr\n",
+    ]);
+    let materialize = |diff_base| {
+        let options = ConflictMaterializeOptions {
+            heuristics: HeuristicConfig {
+                diff_base,
+                ..Default::default()
+            },
+            ..materialize_options(ConflictMarkerStyle::Diff)
+        };
+        materialize_merge_result_to_bytes(&merge, &options)
+    };
+
+    // The second side is closer to the first base than the first side, so the
+    // first side is written as a snapshot. That leaves the last side to be
+    // diffed against the second base, which it shares nothing with.
+    insta::assert_snapshot!(materialize(DiffBaseStrategy::ByIndex), @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1
+    x
+    y
+    c
+    %%%%%%% Changes from base #1 to side #2
+     a
+    -b
+    +y
+     c
+    %%%%%%% Changes from base #2 to side #3
+    -a
+    -y
+    -z
+    +p
+    +q
+    +r
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // Pairing each base with its most similar side results in smaller diffs
+    insta::assert_snapshot!(materialize(DiffBaseStrategy::Nearest), @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base #1 to side #1
+    -a
+    -b
+    +x
+    +y
+     c
+    %%%%%%% Changes from base #2 to side #2
+     a
+     y
+    -z
+    +c
+    +++++++ Contents of side #3
+    p
+    q
+    r
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    insta::assert_snapshot!(materialize(DiffBaseStrategy::Fixed(1)), @r"
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base #1 to side #1
+    -a
+    -b
+    +x
+    +y
+     c
+    +++++++ Contents of side #2
+    a
+    y
+    c
+    %%%%%%% Changes from base #2 to side #3
+    -a
+    -y
+    -z
+    +p
+    +q
+    +r
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    for diff_base in [
+        DiffBaseStrategy::ByIndex,
+        DiffBaseStrategy::Nearest,
+        DiffBaseStrategy::Fixed(0),
+        DiffBaseStrategy::Fixed(1),
+        DiffBaseStrategy::Fixed(5),
+    ] {
+        let hunks = parse_conflict(&materialize(diff_base), 3, MIN_CONFLICT_MARKER_LEN).unwrap();
+        assert_eq!(hunks, [merge.map(|term| term.as_bytes().into())]);
+    }
+}
+
+#[test]
+fn test_assert_roundtrip_stable() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3");
+    let left_id = testutils::write_file(store, path, "left 1\n=======\nline 2\nleft 3");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nline 3\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+
+    for marker_style in [
+        ConflictMarkerStyle::Diff,
+        ConflictMarkerStyle::DiffExperimental,
+        ConflictMarkerStyle::Snapshot,
+        ConflictMarkerStyle::Git,
+    ] {
+        let options = materialize_options(marker_style);
+        assert_roundtrip_stable(store, path, &conflict, &options)
+            .block_on()
+            .unwrap();
+    }
+
+    // Markers which are as long as the "=======" line in the first side can't
+    // be parsed back correctly
+    let options = ConflictMaterializeOptions {
+        marker_len: Some(MIN_CONFLICT_MARKER_LEN),
+        ..materialize_options(ConflictMarkerStyle::Git)
+    };
+    let err = assert_roundtrip_stable(store, path, &conflict, &options)
+        .block_on()
+        .unwrap_err();
+    insta::assert_snapshot!(err, @r#"Materialized conflict at file isn't stable: line 10 was "<<<<<<< Side #1 (Conflict 2 of 2)\n" but became "<<<<<<< Side #1 (Conflict 1 of 1)\n""#);
+}
+
+#[test]
+fn test_update_from_content_with_term_hashes() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "base\n");
+    let left_id = testutils::write_file(store, path, "left\n");
+    let right_id = testutils::write_file(store, path, "right\n");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id.clone())],
+    );
+    let mut options = materialize_options(ConflictMarkerStyle::Snapshot);
+    options.annotations.term_hashes = true;
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1 [26f1adfc]
+    left
+    ------- Contents of base [7479a661]
+    base
+    +++++++ Contents of side #2 [4ac8eb05]
+    right
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+    let update = |content: &str| {
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+
+    // Swap the sections of the two sides, relabel them, and edit the first side
+    let lines = materialized.lines().collect_vec();
+    let reordered = [0, 5, 6, 3, 4, 1, 2, 7]
+        .map(|index| format!("{}\n", lines[index]))
+        .concat()
+        .replace("side #1", "ours")
+        .replace("side #2", "theirs")
+        .replace("left", "edited left");
+    insta::assert_snapshot!(reordered, @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of theirs [4ac8eb05]
+    right
+    ------- Contents of base [7479a661]
+    base
+    +++++++ Contents of ours [26f1adfc]
+    edited left
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+    let edited_left_id = testutils::write_file(store, path, "edited left\n");
+    assert_eq!(
+        update(&reordered),
+        Merge::from_removes_adds(
+            vec![Some(base_id.clone())],
+            vec![Some(edited_left_id.clone()), Some(right_id.clone())],
+        )
+    );
+
+    // Without the hashes, the sections are mapped by position
+    let unhashed = reordered
+        .lines()
+        .map(|line| match line.find(" [") {
+            Some(index) => format!("{}\n", &line[..index]),
+            None => format!("{line}\n"),
+        })
+        .collect::<String>();
+    assert_eq!(
+        update(&unhashed),
+        Merge::from_removes_adds(
+            vec![Some(base_id)],
+            vec![Some(right_id), Some(edited_left_id)],
+        )
+    );
+}
+
+#[test]
+fn test_estimate_materialize_memory() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let file_with_lines = |changed: &[(usize, &str)]| {
+        (0..100)
+            .map(
+                |index| match changed.iter().find(|(line, _)| *line == index) {
+                    Some((_, text)) => format!("{text} line {index}\n"),
+                    None => format!("unchanged line {index}\n"),
+                },
+            )
+            .collect::<String>()
+    };
+    let base_id = testutils::write_file(store, path, &file_with_lines(&[]));
+    let left_id =
+        testutils::write_file(store, path, &file_with_lines(&[(10, "left"), (60, "left")]));
+    let right_id = testutils::write_file(store, path, &file_with_lines(&[(10, "right")]));
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    let contents_size: usize = contents.iter().map(|term| term.len()).sum();
+    let estimate = estimate_materialize_memory(store, path, &conflict)
+        .block_on()
+        .unwrap();
+    assert_eq!(estimate, 3 * contents_size);
+
+    // The actual usage is within a factor of 3 of the estimate
+    for style in [
+        ConflictMarkerStyle::Diff,
+        ConflictMarkerStyle::Snapshot,
+        ConflictMarkerStyle::Git,
+    ] {
+        let output = materialize_conflict_string(store, path, &conflict, style);
+        let actual = contents_size + output.len();
+        assert!(actual <= estimate, "{style:?}: {actual} > {estimate}");
+        assert!(
+            3 * actual >= estimate,
+            "{style:?}: {actual} * 3 < {estimate}"
+        );
+    }
+}
+
+#[test]
+fn test_materialize_full_versions() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "a\nb\nc\nd\ne\n");
+    let left_id = testutils::write_file(store, path, "a\nleft\nc\nd\nleft\n");
+    let right_id = testutils::write_file(store, path, "right\na\nright\nc\nd\ne");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+
+    // Each version includes the changes which were merged cleanly from the other
+    // side
+    let output =
+        materialize_full_versions(&contents, &materialize_options(ConflictMarkerStyle::Diff));
+    insta::assert_snapshot!(output, @r"
+    ======= Contents of side #1
+    right
+    a
+    left
+    c
+    d
+    left
+    ======= Contents of side #2 (no terminating newline)
+    right
+    a
+    right
+    c
+    d
+    e
+    ");
+}
+
+#[test]
+fn test_resolve_to_tree_value() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let symlink_value =
+        |target: &str| TreeValue::Symlink(store.write_symlink(path, target).block_on().unwrap());
+    let file_value = |contents: &str| TreeValue::File {
+        id: testutils::write_file(store, path, contents),
+        executable: true,
+        copy_id: CopyId::placeholder(),
+    };
+    let resolve = |conflict: &MergedTreeValue, content: &str| {
+        resolve_to_tree_value(store, path, conflict, content.as_bytes())
+            .block_on()
+            .unwrap()
+    };
+
+    // All symlinks resolve to a symlink
+    let conflict = Merge::from_vec(vec![
+        Some(symlink_value("left")),
+        Some(symlink_value("base")),
+        Some(symlink_value("right")),
+    ]);
+    assert_eq!(
+        resolve(&conflict, "left"),
+        Merge::normal(symlink_value("left"))
+    );
+
+    // Symlinks and absent values resolve to a symlink
+    let conflict = Merge::from_vec(vec![
+        Some(symlink_value("left")),
+        Some(symlink_value("base")),
+        None,
+    ]);
+    assert_eq!(
+        resolve(&conflict, "new"),
+        Merge::normal(symlink_value("new"))
+    );
+
+    // All files resolve to a file, keeping the executable bit
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("left\n")),
+        Some(file_value("base\n")),
+        Some(file_value("right\n")),
+    ]);
+    assert_eq!(
+        resolve(&conflict, "resolved\n"),
+        Merge::normal(file_value("resolved\n"))
+    );
+
+    // Mixed types are left as a conflict
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("left\n")),
+        Some(file_value("base\n")),
+        Some(symlink_value("right")),
+    ]);
+    assert_eq!(resolve(&conflict, "resolved\n"), conflict);
+}
+
+#[test]
+fn test_materialize_parse_mixed_hunk_styles() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "a\nb\nc\nd\ne\n");
+    let left_id = testutils::write_file(store, path, "left a\nb\nc\nd\n\x01 left\n");
+    let right_id = testutils::write_file(store, path, "right a\nb\nc\nd\n\x01 right\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+
+    // Binary-ish hunks are written as snapshots, other hunks as diffs
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.hunk_style = Some(|hunk| {
+        if hunk.iter().any(|term| term.contains(&1)) {
+            ConflictMarkerStyle::Snapshot
+        } else {
+            ConflictMarkerStyle::Diff
+        }
+    });
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized.replace('\x01', "<SOH>"), @r"
+    <<<<<<< Conflict 1 of 2
+    %%%%%%% Changes from base to side #1
+    -a
+    +left a
+    +++++++ Contents of side #2
+    right a
+    >>>>>>> Conflict 1 of 2 ends
+    b
+    c
+    d
+    <<<<<<< Conflict 2 of 2
+    +++++++ Contents of side #1
+    <SOH> left
+    ------- Contents of base
+    e
+    +++++++ Contents of side #2
+    <SOH> right
+    >>>>>>> Conflict 2 of 2 ends
+    ");
+
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&contents, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+    assert_eq!(
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            materialized.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN
+        )
+        .block_on()
+        .unwrap(),
+        conflict
+    );
+}
+
+#[test]
+fn test_yaml_block_scalar_roundtrip() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "  indented\nline 2\n\nline 4");
+    let left_id = testutils::write_file(store, path, "  indented\nleft 2\n\nline 4");
+    let right_id = testutils::write_file(store, path, "  indented\nright 2\n\nline 4");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let materialized =
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+
+    let document = format!(
+        "name: file\ncontent: {}other: value\n",
+        to_yaml_block_scalar(materialized.as_bytes(), 2)
+    );
+    insta::assert_snapshot!(document, @r"
+    name: file
+    content: |2-
+        indented
+      <<<<<<< Conflict 1 of 1
+      %%%%%%% Changes from base to side #1
+      -line 2
+      +left 2
+      +++++++ Contents of side #2
+      right 2
+      >>>>>>> Conflict 1 of 1 ends
+
+      line 4
+    other: value
+    ");
+
+    // Recover the scalar from the document
+    let scalar = document
+        .strip_prefix("name: file\ncontent: ")
+        .unwrap()
+        .strip_suffix("other: value\n")
+        .unwrap();
+    assert_eq!(
+        parse_yaml_block_scalar(scalar.as_bytes(), 2),
+        Some(materialized.clone().into())
+    );
+
+    // Content with different trailing newlines
+    for content in ["", "a", "a\n", "a\n\n", "\n a\n", "a\r\n\r\n", "\n", "\n\n"] {
+        let scalar = to_yaml_block_scalar(content.as_bytes(), 4);
+        assert_eq!(
+            parse_yaml_block_scalar(&scalar, 4),
+            Some(content.into()),
+            "{scalar:?}"
+        );
+    }
+
+    // Out-of-range indentation is clamped
+    let scalar = to_yaml_block_scalar(b" a\n", 12);
+    assert_eq!(scalar, "|9\n          a\n");
+    assert_eq!(parse_yaml_block_scalar(&scalar, 12), Some(" a\n".into()));
+    assert_eq!(to_yaml_block_scalar(b"a\n", 0), "|\n a\n");
+
+    // Lines without the indentation are rejected
+    assert_eq!(parse_yaml_block_scalar(b"|\n  a\nb\n", 2), None);
+}
+
+#[test]
+fn test_lint_resolution() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "title\n=======\nline 1\nline 2\n");
+    let left_id = testutils::write_file(store, path, "title\n=======\nleft 1\nline 2\n");
+    let right_id = testutils::write_file(store, path, "title\n=======\nright 1\nline 2\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let lint = |resolved: &str| {
+        lint_resolution(store, path, &conflict, resolved.as_bytes())
+            .block_on()
+            .unwrap()
+    };
+
+    // A clean resolution has no warnings. The "=======" line is part of the
+    // sides, so it isn't a leftover marker.
+    assert_eq!(lint("title\n=======\nresolved 1\nline 2\n"), vec![]);
+
+    // Leftover markers
+    assert_eq!(
+        lint("title\n=======\nleft 1\n+++++++ Contents of side #2\nright 1\nline 2\n"),
+        vec![LintWarning {
+            kind: LintWarningKind::LeftoverMarker,
+            line: Some(3),
+        }]
+    );
+
+    // Nothing was resolved. The conflict markers of the materialized conflict are
+    // reported too, and the markers more than double the size of these small
+    // sides.
+    let materialized =
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+    insta::assert_debug_snapshot!(lint(&materialized), @r"
+    [
+        LintWarning {
+            kind: LeftoverMarker,
+            line: Some(
+                2,
+            ),
+        },
+        LintWarning {
+            kind: LeftoverMarker,
+            line: Some(
+                3,
+            ),
+        },
+        LintWarning {
+            kind: LeftoverMarker,
+            line: Some(
+                6,
+            ),
+        },
+        LintWarning {
+            kind: LeftoverMarker,
+            line: Some(
+                8,
+            ),
+        },
+        LintWarning {
+            kind: Unresolved,
+            line: None,
+        },
+        LintWarning {
+            kind: SizeGrowth,
+            line: None,
+        },
+    ]
+    ");
+
+    // Introduced NUL byte
+    assert_eq!(
+        lint("title\n=======\nresolved\0 1\nline 2\n"),
+        vec![LintWarning {
+            kind: LintWarningKind::NulByte,
+            line: Some(2),
+        }]
+    );
+
+    // Huge size growth
+    assert_eq!(
+        lint(&"title\n=======\nresolved 1\nline 2\n".repeat(10)),
+        vec![LintWarning {
+            kind: LintWarningKind::SizeGrowth,
+            line: None,
+        }]
+    );
+}
+
+#[test]
+fn test_lint_resolution_unresolved() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let is_unresolved = |base: &str, left: &str, right: &str| {
+        let conflict = Merge::from_removes_adds(
+            vec![Some(testutils::write_file(store, path, base))],
+            vec![
+                Some(testutils::write_file(store, path, left)),
+                Some(testutils::write_file(store, path, right)),
+            ],
+        );
+        let materialized =
+            materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+        lint_resolution(store, path, &conflict, materialized.as_bytes())
+            .block_on()
+            .unwrap()
+            .iter()
+            .any(|warning| warning.kind == LintWarningKind::Unresolved)
+    };
+
+    assert!(is_unresolved("line 1\n", "left 1\n", "right 1\n"));
+    // The terms have no terminating newline
+    assert!(is_unresolved("line 1", "left 1", "right 1"));
+    // A side contains a line which looks like a conflict marker, so the conflict
+    // is materialized with longer markers
+    assert!(is_unresolved(
+        "line 1\n",
+        "left 1\n+++++++\n",
+        "right 1\n+++++++\n"
+    ));
+}
+
+#[test]
+fn test_parse_conflict_with_version() {
+    // Simulated file from an older version, which always wrote conflict markers
+    // of the minimum length, read with the marker length of the current version
+    let content = indoc! {"
+        line 1
+        <<<<<<< Conflict 1 of 1
+        %%%%%%% Changes from base to side #1
+        -line 2
+        +left 2
+        +++++++ Contents of side #2
+        right 2
+        >>>>>>> Conflict 1 of 1 ends
+        line 3
+    "};
+    let marker_len = MIN_CONFLICT_MARKER_LEN + 4;
+    assert_eq!(parse_conflict(content.as_bytes(), 2, marker_len), None);
+    assert_eq!(
+        detect_conflict_format_version(content.as_bytes(), 2, marker_len),
+        Some(ConflictFormatVersion::LegacyMinMarkerLen)
+    );
+    let expected = Some(vec![
+        Merge::resolved("line 1\n".into()),
+        Merge::from_vec(vec![
+            "left 2\n".into(),
+            "line 2\n".into(),
+            "right 2\n".into(),
+        ]),
+        Merge::resolved("line 3\n".into()),
+    ]);
+    assert_eq!(
+        parse_conflict_with_version(content.as_bytes(), 2, marker_len, None),
+        expected
+    );
+    assert_eq!(
+        parse_conflict_with_version(
+            content.as_bytes(),
+            2,
+            marker_len,
+            Some(ConflictFormatVersion::LegacyMinMarkerLen)
+        ),
+        expected
+    );
+    assert_eq!(
+        parse_conflict_with_version(
+            content.as_bytes(),
+            2,
+            marker_len,
+            Some(ConflictFormatVersion::Current)
+        ),
+        None
+    );
+
+    // Files in the current format are detected as such
+    let content = indoc! {"
+        line 1
+        <<<<<<<<<<< Conflict 1 of 1
+        %%%%%%%%%%% Changes from base to side #1
+        -line 2
+        +left 2
+        +++++++++++ Contents of side #2
+        right 2
+        >>>>>>>>>>> Conflict 1 of 1 ends
+        line 3
+    "};
+    assert_eq!(
+        detect_conflict_format_version(content.as_bytes(), 2, marker_len),
+        Some(ConflictFormatVersion::Current)
+    );
+    assert_eq!(
+        parse_conflict_with_version(content.as_bytes(), 2, marker_len, None),
+        expected
+    );
+    assert_eq!(
+        detect_conflict_format_version(b"line 1\n", 2, marker_len),
+        None
+    );
+}
+
+#[test]
+fn test_duplicate_sides() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "base\n");
+    let left_id = testutils::write_file(store, path, "left\n");
+    let right_id = testutils::write_file(store, path, "right\n");
+    let duplicates = |conflict: &Merge<Option<FileId>>| {
+        duplicate_sides(store, path, conflict).block_on().unwrap()
+    };
+
+    // Two of three sides are identical
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone()), Some(base_id.clone())],
+        vec![
+            Some(left_id.clone()),
+            Some(right_id.clone()),
+            Some(left_id.clone()),
+        ],
+    );
+    assert_eq!(duplicates(&conflict), vec![vec![0, 2]]);
+
+    // No identical sides
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id), Some(right_id)],
+    );
+    assert_eq!(duplicates(&conflict), Vec::<Vec<usize>>::new());
+
+    // An absent side is identical to an empty side
+    let empty_id = testutils::write_file(store, path, "");
+    let conflict = Merge::from_removes_adds(vec![Some(base_id)], vec![None, Some(empty_id)]);
+    assert_eq!(duplicates(&conflict), vec![vec![0, 1]]);
+}
+
+#[test]
+fn test_materialize_conflict_trailing_separator() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nleft 3\n");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nright 3\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.trailing_separator = Some("End of conflicts".to_owned());
+
+    // The separator is only written after the last conflict
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    <<<<<<< Conflict 1 of 2
+    %%%%%%% Changes from base to side #1
+    -line 1
+    +left 1
+    +++++++ Contents of side #2
+    right 1
+    >>>>>>> Conflict 1 of 2 ends
+    line 2
+    <<<<<<< Conflict 2 of 2
+    %%%%%%% Changes from base to side #1
+    -line 3
+    +left 3
+    +++++++ Contents of side #2
+    right 3
+    >>>>>>> Conflict 2 of 2 ends
+    @@@@@@@ End of conflicts
+    ");
+
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&contents, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+
+    // The separator doesn't alter the resolved content after the last conflict
+    let edited = format!("{materialized}line 4\n");
+    let hunks = parse_conflict(edited.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN).unwrap();
+    assert_eq!(hunks.last(), Some(&Merge::resolved("line 4\n".into())));
+
+    // The separator isn't written to the sides when updating the conflict
+    let update = |content: &str| {
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+    let new_base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\nline 4\n");
+    let new_left_id = testutils::write_file(store, path, "left 1\nline 2\nleft 3\nline 4\n");
+    let new_right_id = testutils::write_file(store, path, "right 1\nline 2\nright 3\nline 4\n");
+    assert_eq!(
+        update(&edited),
+        Merge::from_removes_adds(
+            vec![Some(new_base_id)],
+            vec![Some(new_left_id), Some(new_right_id)]
+        )
+    );
+}
+
+#[test]
+fn test_materialize_conflict_base_block() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2 (no terminating newline)\n");
+    let left_id = testutils::write_file(store, path, "left 1\nleft 2\n");
+    let right_id = testutils::write_file(store, path, "right 1\nright 2\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.base_block = true;
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    @@@@@@@ ----- base -----
+    @@@@@@@ | line 1
+    @@@@@@@ | line 2 (no terminating newline)
+    @@@@@@@ ----- end of base -----
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -line 1
+    -line 2 (no terminating newline)
+    +left 1
+    +left 2
+    +++++++ Contents of side #2
+    right 1
+    right 2
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // The base block is ignored when parsing
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&contents, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+    // A quoted line which looks like a note about a missing newline doesn't
+    // affect the preceding content
+    let edited = format!("resolved\n{materialized}");
+    assert_eq!(
+        parse_conflict(edited.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN).unwrap()[0],
+        Merge::resolved("resolved\n".into())
+    );
+
+    // The base block isn't written to the sides when updating the conflict
+    let update = |content: &str| {
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+    let new_base_id = testutils::write_file(
+        store,
+        path,
+        "resolved\nline 1\nline 2 (no terminating newline)\n",
+    );
+    let new_left_id = testutils::write_file(store, path, "resolved\nleft 1\nleft 2\n");
+    let new_right_id = testutils::write_file(store, path, "resolved\nright 1\nright 2\n");
+    assert_eq!(
+        update(&edited),
+        Merge::from_removes_adds(
+            vec![Some(new_base_id)],
+            vec![Some(new_left_id), Some(new_right_id)]
+        )
+    );
+}
+
+#[test]
+fn test_materialize_conflict_rewritten_side() {
+    // The first side is always written as a snapshot, so the other sides are
+    // written as diffs
+    let merge = Merge::from_vec(vec![
+        "a\nb\nc\nd\ne\n",
+        "a\nb\nc\nd\n",
+        "completely\ndifferent\ncontent\n",
+        "a\nb\nc\nd\n",
+        "a\nb\nC\nd\n",
+    ]);
+    let materialize = |min_side_diff_quality| {
+        let options = ConflictMaterializeOptions {
+            heuristics: HeuristicConfig {
+                min_side_diff_quality,
+                ..Default::default()
+            },
+            ..materialize_options(ConflictMarkerStyle::DiffExperimental)
+        };
+        materialize_merge_result_to_bytes(&merge, &options)
+    };
+
+    // By default, the rewritten side is written as a diff
+    insta::assert_snapshot!(materialize(None), @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1
+    a
+    b
+    c
+    d
+    e
+    %%%%%%% Changes from base #1 to side #2
+    -a
+    -b
+    -c
+    -d
+    +completely
+    +different
+    +content
+    %%%%%%% Changes from base #2 to side #3
+     a
+     b
+    -c
+    +C
+     d
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // The rewritten side is written as a snapshot, while the other side is still
+    // written as a diff
+    let materialized = materialize(Some(0.2));
+    insta::assert_snapshot!(materialized, @r"
+    <<<<<<< Conflict 1 of 1
+    +++++++ Contents of side #1
+    a
+    b
+    c
+    d
+    e
+    ------- Contents of base #1
+    a
+    b
+    c
+    d
+    +++++++ Contents of side #2 (rewritten)
+    completely
+    different
+    content
+    %%%%%%% Changes from base #2 to side #3
+     a
+     b
+    -c
+    +C
+     d
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+    assert_eq!(
+        parse_conflict(&materialized, 3, MIN_CONFLICT_MARKER_LEN),
+        Some(vec![merge.map(|term| term.as_bytes().into())])
+    );
+}
+
+#[test]
+fn test_reassemble_conflict() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "base\n");
+    let left_id = testutils::write_file(store, path, "left\n");
+    let right_id = testutils::write_file(store, path, "right\n");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id)],
+    );
+    let reassemble = |conflict: &Merge<Option<FileId>>, hunks: &[Merge<BString>]| {
+        reassemble_conflict(store, path, conflict, hunks).block_on()
+    };
+
+    let hunks = vec![
+        Merge::resolved("header\n".into()),
+        Merge::from_vec(vec![
+            "left 1\n".into(),
+            "base 1\n".into(),
+            "right 1\n".into(),
+        ]),
+        Merge::resolved("middle\n".into()),
+        Merge::from_vec(vec![
+            "left 2\n".into(),
+            "base 2\n".into(),
+            "right 2\n".into(),
+        ]),
+    ];
+    assert_eq!(
+        reassemble(&conflict, &hunks).unwrap(),
+        Some(Merge::from_removes_adds(
+            vec![Some(testutils::write_file(
+                store,
+                path,
+                "header\nbase 1\nmiddle\nbase 2\n"
+            ))],
+            vec![
+                Some(testutils::write_file(
+                    store,
+                    path,
+                    "header\nleft 1\nmiddle\nleft 2\n"
+                )),
+                Some(testutils::write_file(
+                    store,
+                    path,
+                    "header\nright 1\nmiddle\nright 2\n"
+                )),
+            ],
+        ))
+    );
+
+    // An absent term can't get content
+    let conflict = Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), None]);
+    assert_eq!(reassemble(&conflict, &hunks).unwrap(), None);
+
+    // The hunks must have as many sides as the conflict
+    let hunks = vec![Merge::from_vec(vec![
+        "a\n".into(),
+        "b\n".into(),
+        "c\n".into(),
+        "d\n".into(),
+        "e\n".into(),
+    ])];
+    assert_matches!(reassemble(&conflict, &hunks), Err(BackendError::Other(_)));
+}
+
+#[test]
+fn test_materialize_merge_result_with_timings() {
+    let merge = Merge::from_vec(vec![
+        "left 1\nline 2\nleft 3\n",
+        "line 1\nline 2\nline 3\n",
+        "right 1\nline 2\nright 3\n",
+    ]);
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let (content, timings) = materialize_merge_result_with_timings(&merge, &options);
+    assert_eq!(timings.len(), 2);
+    assert_eq!(content, materialize_merge_result_to_bytes(&merge, &options));
+
+    // Resolved merges have no timings
+    let merge = Merge::from_vec(vec!["left\n", "base\n", "base\n"]);
+    let (content, timings) = materialize_merge_result_with_timings(&merge, &options);
+    assert_eq!(content, "left\n");
+    assert_eq!(timings, vec![]);
+}
+
+#[test]
+fn test_simplify_conflict() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "a\nb\nc\nd\ne\nf\n");
+    let left_id = testutils::write_file(store, path, "A\nB\nc\nd\ne\nleft\n");
+    let right_id = testutils::write_file(store, path, "a\nb\nc\nD\ne\nright\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let simplify = |conflict: &Merge<Option<FileId>>| {
+        simplify_conflict(store, path, conflict).block_on().unwrap()
+    };
+
+    // The changes which merge cleanly are written to all terms, so the terms only
+    // differ in the last line
+    let simplified = simplify(&conflict);
+    assert_eq!(
+        simplified,
+        Merge::from_removes_adds(
+            vec![Some(testutils::write_file(
+                store,
+                path,
+                "A\nB\nc\nD\ne\nf\n"
+            ))],
+            vec![
+                Some(testutils::write_file(store, path, "A\nB\nc\nD\ne\nleft\n")),
+                Some(testutils::write_file(store, path, "A\nB\nc\nD\ne\nright\n")),
+            ],
+        )
+    );
+    assert_eq!(
+        materialize_conflict_string(store, path, &simplified, ConflictMarkerStyle::Diff),
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff)
+    );
+    // Simplifying again doesn't change anything
+    assert_eq!(simplify(&simplified), simplified);
+
+    // A conflict which can be merged is resolved
+    let base_id = testutils::write_file(store, path, "a\nb\nc\n");
+    let left_id = testutils::write_file(store, path, "A\nb\nc\n");
+    let right_id = testutils::write_file(store, path, "a\nb\nC\n");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id.clone()), Some(right_id)],
+    );
+    assert_eq!(
+        simplify(&conflict),
+        Merge::normal(testutils::write_file(store, path, "A\nb\nC\n"))
+    );
+
+    // An absent term can't get the merged content
+    let conflict = Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), None]);
+    assert_eq!(simplify(&conflict), conflict);
+}
+
+#[test]
+fn test_materialize_conflict_side_by_side() {
+    let base = indoc! {"
+        line 1
+        line 2
+        line 3
+        line 4
+    "};
+    let left = indoc! {"
+        line 1
+        left 2
+        both 3
+        line 4
+    "};
+    let right = indoc! {"
+        line 1
+        right 2
+        right 2.5
+        both 3
+        line 4 which is too long to fit
+    "};
+    let conflict = Merge::from_vec(vec![left, base, right]);
+    let output = materialize_conflict_side_by_side(
+        &conflict,
+        &materialize_options(ConflictMarkerStyle::Diff),
+        40,
+    );
+    insta::assert_snapshot!(output, @r"
+    line 1             | line 1
+    <<<<<<< Conflict 1 of 1
+    side #1            | side #2
+    left 2             * right 2
+                       * right 2.5
+    both 3             | both 3
+    line 4             * line 4 which is t>
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // Both columns contain the content of their side
+    let (left_column, right_column): (String, String) = output
+        .to_str()
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains(['|', '*']))
+        .map(|line| {
+            let (left, right) = line.split_at(18);
+            (
+                format!("{}\n", left.trim_end()),
+                format!("{}\n", right[3..].trim_end()),
+            )
+        })
+        .unzip();
+    insta::assert_snapshot!(left_column, @r"
+    line 1
+    side #1
+    left 2
+
+    both 3
+    line 4
+    ");
+    insta::assert_snapshot!(right_column, @r"
+    line 1
+    side #2
+    right 2
+    right 2.5
+    both 3
+    line 4 which is t>
+    ");
+}
+
+#[test]
+fn test_required_marker_length() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\n");
+    let left_id = testutils::write_file(store, path, "line 1\nleft 2\n");
+    let right_id = testutils::write_file(store, path, "line 1\nright 2\n");
+    let conflict = Merge::from_removes_adds(
+        vec![Some(base_id.clone())],
+        vec![Some(left_id), Some(right_id)],
+    );
+    let required_len = |conflict: &Merge<Option<FileId>>| {
+        required_marker_length(store, path, conflict)
+            .block_on()
+            .unwrap()
+    };
+    assert_eq!(required_len(&conflict), MIN_CONFLICT_MARKER_LEN);
+
+    // A side containing a line which looks like a longer conflict marker forces
+    // longer markers
+    let left_id = testutils::write_file(store, path, "line 1\n========== left 2\n");
+    let right_id = testutils::write_file(store, path, "line 1\n++++++++ right 2\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    assert_eq!(required_len(&conflict), 11);
+
+    // The conflict can be parsed back when materialized with that length
+    let options = ConflictMaterializeOptions {
+        marker_len: Some(11),
+        ..materialize_options(ConflictMarkerStyle::Diff)
+    };
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    let hunks = parse_conflict(materialized.as_bytes(), 2, 11).unwrap();
+    assert_eq!(
+        hunks.last().unwrap().adds().collect_vec(),
+        ["========== left 2\n", "++++++++ right 2\n"]
+    );
+}
+
+#[test]
+fn test_materialize_conflict_attribution() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\nline 4\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nline 3\n");
+    let right_id = testutils::write_file(
+        store,
+        path,
+        "right 1\nline 2\nline 3\nline 4 (no terminating newline)\n",
+    );
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.attribution = true;
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    @@@@@@@ ----- change 1 of 1 -----
+    @@@@@@@ base: line 1
+    @@@@@@@ local: left 1
+    @@@@@@@ remote: right 1
+    @@@@@@@ ----- end of changes -----
+    <<<<<<< Conflict 1 of 2
+    %%%%%%% Changes from base to side #1
+    -line 1
+    +left 1
+    +++++++ Contents of side #2
+    right 1
+    >>>>>>> Conflict 1 of 2 ends
+    line 2
+    line 3
+    @@@@@@@ ----- change 1 of 1 -----
+    @@@@@@@ base: line 4
+    @@@@@@@ local:
+    @@@@@@@ remote: line 4 (no terminating newline)
+    @@@@@@@ ----- end of changes -----
+    <<<<<<< Conflict 2 of 2
+    %%%%%%% Changes from base to side #1
+    -line 4
+    +++++++ Contents of side #2
+    line 4 (no terminating newline)
+    >>>>>>> Conflict 2 of 2 ends
+    ");
+
+    // The attribution is ignored when parsing, including the line which looks
+    // like a note about a missing newline
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&contents, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+
+    // The attribution isn't written to the file when updating the conflict
+    let update = |content: &str| {
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+    let (first_conflict, _) = materialized.split_once("line 3\n").unwrap();
+    let edited = format!("{first_conflict}line 3\nline 4\n");
+    assert_eq!(
+        update(&edited),
+        Merge::from_removes_adds(
+            vec![Some(testutils::write_file(
+                store,
+                path,
+                "line 1\nline 2\nline 3\nline 4\n"
+            ))],
+            vec![
+                Some(testutils::write_file(
+                    store,
+                    path,
+                    "left 1\nline 2\nline 3\nline 4\n"
+                )),
+                Some(testutils::write_file(
+                    store,
+                    path,
+                    "right 1\nline 2\nline 3\nline 4\n"
+                )),
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_partial_resolution() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, path, "left 1\nline 2\nleft 3\n");
+    let right_id = testutils::write_file(store, path, "right 1\nline 2\nright 3\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+    let materialized =
+        materialize_conflict_string(store, path, &conflict, ConflictMarkerStyle::Diff);
+
+    // Resolve the first of the two conflicts
+    let (_, second_conflict) = materialized.split_once("line 2\n").unwrap();
+    let edited = format!("resolved 1\nline 2\n{second_conflict}");
+    let partial = partial_resolution(
+        store,
+        path,
+        &conflict,
+        edited.as_bytes(),
+        MIN_CONFLICT_MARKER_LEN,
+    )
+    .block_on()
+    .unwrap();
+    assert_eq!(partial.resolved, ["resolved 1\nline 2\n"]);
+    assert_eq!(partial.remaining.num_sides(), 2);
+    insta::assert_snapshot!(
+        materialize_conflict_string(store, path, &partial.remaining, ConflictMarkerStyle::Diff),
+        @r"
+    resolved 1
+    line 2
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -line 3
+    +left 3
+    +++++++ Contents of side #2
+    right 3
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // Resolving the remaining conflict resolves the file
+    let partial = partial_resolution(
+        store,
+        path,
+        &partial.remaining,
+        b"resolved 1\nline 2\nresolved 3\n",
+        MIN_CONFLICT_MARKER_LEN,
+    )
+    .block_on()
+    .unwrap();
+    assert_eq!(partial.resolved, ["resolved 1\nline 2\nresolved 3\n"]);
+    assert_eq!(
+        partial.remaining,
+        Merge::normal(testutils::write_file(
+            store,
+            path,
+            "resolved 1\nline 2\nresolved 3\n"
+        ))
+    );
+}
+
+#[test]
+fn test_materialize_conflict_hunk_counters() {
+    // Five conflicts separated by unchanged lines
+    let base = "a\n-\nb\n-\nc\n-\nd\n-\ne\n";
+    let left = "a1\n-\nb1\n-\nc1\n-\nd1\n-\ne1\n";
+    let right = "a2\n-\nb2\n-\nc2\n-\nd2\n-\ne2\n";
+    let merge = Merge::from_vec(vec![left, base, right]);
+    for marker_style in [ConflictMarkerStyle::Diff, ConflictMarkerStyle::Git] {
+        let mut options = materialize_options(marker_style);
+        options.annotations.hunk_counters = true;
+        let materialized = materialize_merge_result_to_bytes(&merge, &options);
+        let start_markers = materialized
+            .lines()
+            .filter(|line| line.starts_with(b"<<<<<<<"))
+            .map(|line| line.to_str().unwrap())
+            .collect_vec();
+        assert_eq!(start_markers.len(), 5);
+        for (index, marker) in (1..).zip(start_markers) {
+            assert!(marker.ends_with(&format!(" [{index}/5]")), "{marker}");
+        }
+
+        // The counters are ignored when parsing
+        let MergeResult::Conflict(hunks) = files::merge_hunks(&merge, &options.merge) else {
+            panic!("expected conflict");
+        };
+        let parsed = parse_conflict(&materialized, 2, MIN_CONFLICT_MARKER_LEN).unwrap();
+        assert!(
+            parsed
+                .iter()
+                .all(|hunk| hunk.is_resolved() || hunk.num_sides() == 2)
+        );
+        assert_eq!(parsed, hunks);
+    }
+
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.hunk_counters = true;
+    let materialized =
+        materialize_merge_result_to_bytes(&Merge::from_vec(vec!["a\n", "b\n", "c\n"]), &options);
+    insta::assert_snapshot!(materialized, @r"
+    <<<<<<< Conflict 1 of 1 [1/1]
+    %%%%%%% Changes from base to side #1
+    -b
+    +a
+    +++++++ Contents of side #2
+    c
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // The counters are ignored when updating the conflict from the content
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+    let path = repo_path("file");
+    let conflict = Merge::from_vec(vec![
+        Some(testutils::write_file(store, path, "a\n")),
+        Some(testutils::write_file(store, path, "b\n")),
+        Some(testutils::write_file(store, path, "c\n")),
+    ]);
+    let update = |content: &[u8]| {
+        update_from_content(&conflict, store, path, content, MIN_CONFLICT_MARKER_LEN)
+            .block_on()
+            .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+    let edited = materialized.replace(b"\nc\n", b"\nc2\n");
+    assert_eq!(
+        update(&edited),
+        Merge::from_vec(vec![
+            Some(testutils::write_file(store, path, "a\n")),
+            Some(testutils::write_file(store, path, "b\n")),
+            Some(testutils::write_file(store, path, "c2\n")),
+        ])
+    );
+}
+
+#[test]
+fn test_apply_edit_script() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let file_value = |contents: &str| TreeValue::File {
+        id: testutils::write_file(store, path, contents),
+        executable: false,
+        copy_id: CopyId::placeholder(),
+    };
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("line 1\nleft 2\nline 3\n")),
+        Some(file_value("line 1\nline 2\nline 3\n")),
+        Some(file_value("line 1\nright 2\nline 3\nright 4\n")),
+    ]);
+    let apply = |side: usize, ops: &[LineOp]| {
+        apply_edit_script(store, path, &conflict, side, ops).block_on()
+    };
+
+    // Delete a line and replace another one of the second side. The lines
+    // without ops are kept.
+    let ops = [
+        LineOp::Keep,
+        LineOp::Delete,
+        LineOp::Replace("line 3\nline 3.5\n".into()),
+    ];
+    assert_eq!(
+        apply(1, &ops).unwrap(),
+        file_value("line 1\nline 3\nline 3.5\nright 4\n")
+    );
+
+    // There can't be more ops than lines
+    let ops = [LineOp::Keep, LineOp::Keep, LineOp::Keep, LineOp::Delete];
+    assert_matches!(apply(0, &ops), Err(BackendError::Other(_)));
+
+    // The side must exist
+    assert_matches!(apply(2, &[]), Err(BackendError::Other(_)));
+}
+
+#[test]
+fn test_is_reorder_only() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let reorder_only = |base: &str, left: &str, right: &str| {
+        let conflict = Merge::from_vec(vec![
+            Some(testutils::write_file(store, path, left)),
+            Some(testutils::write_file(store, path, base)),
+            Some(testutils::write_file(store, path, right)),
+        ]);
+        is_reorder_only(store, path, &conflict).block_on().unwrap()
+    };
+
+    // Both sides are permutations of the base
+    assert!(reorder_only("a\nb\nc\n", "b\na\nc\n", "a\nc\nb\n"));
+    // The last line has no terminating newline
+    assert!(reorder_only("a\nb\nc", "c\nb\na", "b\nc\na"));
+    // A line was duplicated
+    assert!(!reorder_only("a\nb\nc\n", "b\na\nc\n", "a\nc\nb\nb\n"));
+    // A line was changed
+    assert!(!reorder_only("a\nb\nc\n", "b\na\nc\n", "a\nc\nB\n"));
+    // Identical terms aren't a reordering
+    assert!(!reorder_only("a\nb\n", "a\nb\n", "a\nb\n"));
+}
+
+#[test]
+fn test_materialize_conflict_instructions() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let base_id = testutils::write_file(store, path, "line 1\nline 2\n");
+    let left_id = testutils::write_file(store, path, "line 1\nleft 2\n");
+    let right_id = testutils::write_file(store, path, "line 1\nright 2\n");
+    let conflict =
+        Merge::from_removes_adds(vec![Some(base_id)], vec![Some(left_id), Some(right_id)]);
+
+    // No instructions by default
+    let options = materialize_options(ConflictMarkerStyle::Diff);
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    assert!(materialized.starts_with("line 1\n"));
+
+    let mut options = materialize_options(ConflictMarkerStyle::Diff);
+    options.annotations.instructions = Some(DEFAULT_CONFLICT_INSTRUCTIONS.to_owned());
+    let materialized = materialize_conflict_string_with_options(store, path, &conflict, &options);
+    insta::assert_snapshot!(materialized, @r"
+    @@@@@@@ This file has conflicts. To resolve a conflict, replace everything from its
+    @@@@@@@ <<<<<<< marker to its >>>>>>> marker with the content you want. Comment
+    @@@@@@@ lines like this one are ignored.
+    line 1
+    <<<<<<< Conflict 1 of 1
+    %%%%%%% Changes from base to side #1
+    -line 2
+    +left 2
+    +++++++ Contents of side #2
+    right 2
+    >>>>>>> Conflict 1 of 1 ends
+    ");
+
+    // The instructions are ignored when parsing
+    let contents = extract_as_single_hunk(&conflict, store, path)
+        .block_on()
+        .unwrap();
+    let MergeResult::Conflict(hunks) = files::merge_hunks(&contents, &options.merge) else {
+        panic!("expected conflict");
+    };
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 2, MIN_CONFLICT_MARKER_LEN),
+        Some(hunks)
+    );
+
+    // The instructions aren't written to the file when updating the conflict,
+    // even if the user resolved the conflict without removing them
+    let update = |content: &str| {
+        update_from_content(
+            &conflict,
+            store,
+            path,
+            content.as_bytes(),
+            MIN_CONFLICT_MARKER_LEN,
+        )
+        .block_on()
+        .unwrap()
+    };
+    assert_eq!(update(&materialized), conflict);
+    let (instructions, _) = materialized.split_once("line 1\n").unwrap();
+    let resolved = format!("{instructions}line 1\nresolved 2\n");
+    assert_eq!(
+        update(&resolved),
+        Merge::normal(testutils::write_file(store, path, "line 1\nresolved 2\n"))
+    );
+}
+
+#[test]
+fn test_encode_decode_hunks() {
+    let merge = Merge::from_vec(vec![
+        "line 1\nleft 2\nline 3\nleft 4",
+        "line 1\nline 2\nline 3\nline 4\n",
+        "line 1\nright 2\nline 3\n",
+    ]);
+    let MergeResult::Conflict(hunks) = files::merge_hunks(
+        &merge,
+        &materialize_options(ConflictMarkerStyle::Diff).merge,
+    ) else {
+        panic!("expected conflict");
+    };
+    assert!(hunks.iter().any(|hunk| hunk.is_resolved()));
+    assert!(hunks.iter().any(|hunk| !hunk.is_resolved()));
+    let encoded = encode_hunks(&hunks);
+    assert_eq!(decode_hunks(&encoded), Ok(hunks.clone()));
+    assert_eq!(decode_hunks(&encode_hunks(&[])), Ok(vec![]));
+
+    // Invalid data is rejected
+    assert_eq!(decode_hunks(b""), Err(HunkDecodeError::Truncated));
+    assert_eq!(
+        decode_hunks(&encoded[..encoded.len() - 1]),
+        Err(HunkDecodeError::Truncated)
+    );
+    assert_eq!(
+        decode_hunks(&[encoded.as_slice(), b"x"].concat()),
+        Err(HunkDecodeError::TrailingData)
+    );
+    assert_eq!(
+        decode_hunks(&[&[2], &encoded[1..]].concat()),
+        Err(HunkDecodeError::UnsupportedVersion(2))
+    );
+    assert_eq!(
+        decode_hunks(&[1, 1, 0, 0, 0, 2, 0, 0, 0]),
+        Err(HunkDecodeError::EvenNumTerms(2))
+    );
+}
+
+#[test]
+fn test_semantic_resolve() {
+    // Merges the sides by taking the lines of all sides, without duplicates
+    struct UniqueLinesMerger;
+
+    impl SemanticMerger for UniqueLinesMerger {
+        fn merge(&self, _base: &[u8], sides: &[&[u8]]) -> Option<Vec<u8>> {
+            let lines = sides
+                .iter()
+                .flat_map(|side| side.lines_with_terminator())
+                .unique()
+                .collect_vec();
+            Some(lines.concat())
+        }
+    }
+
+    // Refuses to merge anything
+    struct FailingMerger;
+
+    impl SemanticMerger for FailingMerger {
+        fn merge(&self, _base: &[u8], _sides: &[&[u8]]) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let file_value = |contents: &str| TreeValue::File {
+        id: testutils::write_file(store, path, contents),
+        executable: false,
+        copy_id: CopyId::placeholder(),
+    };
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("a\nb\nleft\n")),
+        Some(file_value("a\nb\n")),
+        Some(file_value("a\nb\nright\n")),
+    ]);
+    assert_eq!(
+        semantic_resolve(store, path, &conflict, &UniqueLinesMerger)
+            .block_on()
+            .unwrap(),
+        Some(file_value("a\nb\nleft\nright\n"))
+    );
+    assert_eq!(
+        semantic_resolve(store, path, &conflict, &FailingMerger)
+            .block_on()
+            .unwrap(),
+        None
+    );
+
+    // Conflicts with different bases can't be merged
+    let conflict = Merge::from_vec(vec![
+        Some(file_value("a\nleft\n")),
+        Some(file_value("a\n")),
+        Some(file_value("a\nmiddle\n")),
+        Some(file_value("b\n")),
+        Some(file_value("b\nright\n")),
+    ]);
+    assert_eq!(
+        semantic_resolve(store, path, &conflict, &UniqueLinesMerger)
+            .block_on()
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_validate_markers() {
+    // Balanced conflicts in both marker styles
+    let merge = Merge::from_vec(vec![
+        "a\nleft\nb\nleft\n",
+        "a\nbase\nb\nbase\n",
+        "a\nright\nb\nright\n",
+    ]);
+    for marker_style in [ConflictMarkerStyle::Diff, ConflictMarkerStyle::Git] {
+        let materialized =
+            materialize_merge_result_to_bytes(&merge, &materialize_options(marker_style));
+        assert_eq!(validate_markers(&materialized), Ok(()));
+    }
+    assert_eq!(validate_markers(b"no conflicts\n"), Ok(()));
+    // Section markers outside of conflicts are content
+    assert_eq!(validate_markers(b"title\n=======\n"), Ok(()));
+
+    // A conflict which is never closed
+    let content = indoc! {b"
+        line 1
+        <<<<<<< Conflict 1 of 1
+        +++++++ Contents of side #1
+        left
+        %%%%%%% Changes from base to side #2
+        -base
+        +right
+    "};
+    let error = validate_markers(content).unwrap_err();
+    assert_eq!(
+        error,
+        MarkerError {
+            kind: MarkerErrorKind::Unterminated,
+            line: 1,
+            offset: 7,
+        }
+    );
+    insta::assert_snapshot!(error, @"Conflict is never closed at line 2");
+
+    // An end marker outside of a conflict
+    let content = indoc! {b"
+        line 1
+        >>>>>>> Conflict 1 of 1 ends
+        line 2
+    "};
+    assert_eq!(
+        validate_markers(content),
+        Err(MarkerError {
+            kind: MarkerErrorKind::UnexpectedEnd,
+            line: 1,
+            offset: 7,
+        })
+    );
+
+    // A conflict starting inside a conflict
+    let content = indoc! {b"
+        <<<<<<< Conflict 1 of 2
+        +++++++ Contents of side #1
+        left
+        <<<<<<< Conflict 2 of 2
+    "};
+    assert_matches!(
+        validate_markers(content),
+        Err(MarkerError {
+            kind: MarkerErrorKind::NestedStart,
+            line: 3,
+            ..
+        })
+    );
+
+    // A conflict without sections
+    let content = indoc! {b"
+        <<<<<<< Conflict 1 of 1
+        left
+        >>>>>>> Conflict 1 of 1 ends
+    "};
+    assert_matches!(
+        validate_markers(content),
+        Err(MarkerError {
+            kind: MarkerErrorKind::NoSections,
+            line: 0,
+            ..
+        })
+    );
+
+    // Markers shorter than the first start marker are content
+    let content = indoc! {b"
+        <<<<<<<<<<< Conflict 1 of 1
+        +++++++++++ Contents of side #1
+        >>>>>>> not a marker
+        %%%%%%%%%%% Changes from base to side #2
+        >>>>>>>>>>> Conflict 1 of 1 ends
+    "};
+    assert_eq!(validate_markers(content), Ok(()));
+}
+
+fn materialize_conflict_string(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    marker_style: ConflictMarkerStyle,
+) -> String {
+    materialize_conflict_string_with_options(
+        store,
+        path,
+        conflict,
+        &materialize_options(marker_style),
+    )
+}
+
+fn materialize_conflict_string_with_options(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Merge<Option<FileId>>,
+    options: &ConflictMaterializeOptions,
+) -> String {
+    let contents = extract_as_single_hunk(conflict, store, path)
+        .block_on()
+        .unwrap();
+    String::from_utf8(materialize_merge_result_to_bytes(&contents, options).into()).unwrap()
+}
+
+fn materialize_options(marker_style: ConflictMarkerStyle) -> ConflictMaterializeOptions {
+    ConflictMaterializeOptions::new(
+        marker_style,
+        MergeOptions {
+            hunk_level: FileMergeHunkLevel::Line,
+            same_change: SameChange::Accept,
+            ignore_trailing_newline: false,
+        },
+    )
+}
+
+#[test]
+fn test_resolve_with_command() {
+    let test_repo = TestRepo::init();
+    let store = test_repo.repo.store();
+
+    let path = repo_path("file");
+    let file_value = |content: &str, copy_id: &[u8]| {
+        Some(TreeValue::File {
+            id: testutils::write_file(store, path, content),
+            executable: false,
+            copy_id: CopyId::new(copy_id.to_vec()),
+        })
+    };
+    let resolve = |conflict: &MergedTreeValue| {
+        // `git merge-file` writes the merged content to the first file, and exits
+        // with a non-zero status if there are conflicts
+        let cmd = MergeCommand {
+            program: "git".to_owned(),
+            args: ["merge-file", "-L", "%P", "%A", "%O", "%B"]
+                .map(str::to_owned)
+                .to_vec(),
+        };
+        resolve_with_command(store, path, conflict, &cmd)
+            .block_on()
+            .unwrap()
+    };
+
+    let conflict = Merge::from_vec(vec![
+        file_value("left 1\nline 2\nline 3\n", b""),
+        file_value("line 1\nline 2\nline 3\n", b""),
+        file_value("line 1\nline 2\nright 3\n", b""),
+    ]);
+    assert_eq!(
+        resolve(&conflict),
+        file_value("left 1\nline 2\nright 3\n", b"")
+    );
+
+    // A failing command leaves the conflict unresolved
+    let conflict = Merge::from_vec(vec![
+        file_value("left 1\nline 2\nline 3\n", b""),
+        file_value("line 1\nline 2\nline 3\n", b""),
+        file_value("right 1\nline 2\nline 3\n", b""),
+    ]);
+    assert_eq!(resolve(&conflict), None);
+
+    // The copy id is preserved if all sides agree
+    let conflict = Merge::from_vec(vec![
+        file_value("left 1\nline 2\nline 3\n", b"copy"),
+        file_value("line 1\nline 2\nline 3\n", b"copy"),
+        file_value("line 1\nline 2\nright 3\n", b"copy"),
+    ]);
+    assert_eq!(
+        resolve(&conflict),
+        file_value("left 1\nline 2\nright 3\n", b"copy")
+    );
+
+    // The conflict is kept if the copy ids disagree
+    let conflict = Merge::from_vec(vec![
+        file_value("left 1\nline 2\nline 3\n", b"copy 1"),
+        file_value("line 1\nline 2\nline 3\n", b"copy"),
+        file_value("line 1\nline 2\nright 3\n", b"copy 2"),
+    ]);
+    assert_eq!(resolve(&conflict), None);
 }